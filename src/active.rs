@@ -1,92 +1,218 @@
+use crate::board::{BUFFER_HEIGHT, Board};
 use crate::tetromino::Rotation::*;
 use crate::tetromino::{Color, Rotation, Tetromino};
 
-pub const BOARD_WIDTH: usize = 10;
-pub const BOARD_HEIGHT: usize = 20;
-static HIDDEN_ROWS: [usize; 3] = [usize::MAX - 2, usize::MAX - 1, usize::MAX];
-
+#[derive(Clone)]
 pub struct ActiveTetromino {
-    x: usize,
-    y: usize,
+    x: i32,
+    y: i32,
     rotation: Rotation,
     shape: &'static Tetromino,
+    buffer: usize,
+}
+
+// offsets tried in turn when a half turn is blocked: up, sideways and
+// diagonally up
+static HALF_TURN_KICKS: [(i32, i32); 6] = [(0, 0), (0, -1), (1, 0), (-1, 0), (1, -1), (-1, -1)];
+
+// the top of a tetromino spawned right above the visible rows, as far as
+// the buffer zone allows
+fn spawn_row(shape: &Tetromino, buffer: usize) -> i32 {
+    let row = (BUFFER_HEIGHT - buffer.min(2)) as i32 - 1 + shape.spawn_offset().1;
+    // high pieces are moved down to stay within the buffer zone
+    let top = shape.points(R0).iter().map(|&(_, y)| y).min().unwrap_or(0) as i32;
+    row.max((BUFFER_HEIGHT - buffer) as i32 - top)
 }
 
 impl ActiveTetromino {
-    pub fn new(shape: &'static Tetromino) -> ActiveTetromino {
+    /// Spawns the tetromino centered on a board of the given width
+    pub fn new(shape: &'static Tetromino, width: usize) -> ActiveTetromino {
+        ActiveTetromino::spawn(shape, width, BUFFER_HEIGHT)
+    }
+
+    /// Spawns the tetromino which may enter the given number of rows of the
+    /// vanishing zone. Below two rows it spawns within the visible rows.
+    pub fn spawn(shape: &'static Tetromino, width: usize, buffer: usize) -> ActiveTetromino {
+        let buffer = buffer.min(BUFFER_HEIGHT);
         ActiveTetromino {
-            x: BOARD_WIDTH / 2 - 2,
-            y: HIDDEN_ROWS[0],
+            x: width as i32 / 2 - 2 + shape.spawn_offset().0,
+            y: spawn_row(shape, buffer),
             rotation: R0,
             shape,
+            buffer,
+        }
+    }
+
+    /// The same tetromino limited to the given number of rows of the vanishing zone
+    pub fn with_buffer(self, buffer: usize) -> ActiveTetromino {
+        ActiveTetromino {
+            buffer: buffer.min(BUFFER_HEIGHT),
+            ..self
         }
     }
 
+    /// The cells covered by the tetromino. They lie on the board as long as
+    /// the tetromino fits.
     pub fn as_points(&self) -> Vec<(usize, usize)> {
+        self.cells(self.x, self.y, self.rotation)
+            .map(|(x, y)| (x as usize, y as usize))
+            .collect()
+    }
+
+    fn cells(&self, x: i32, y: i32, rotation: Rotation) -> impl Iterator<Item = (i32, i32)> {
         self.shape
-            .points(self.rotation)
+            .points(rotation)
             .iter()
-            .map(|&(x, y)| (x.wrapping_add(self.x), y.wrapping_add(self.y)))
-            .collect()
+            .map(move |&(dx, dy)| (x + dx as i32, y + dy as i32))
+    }
+
+    /// Places the tetromino so that it covers exactly the given points
+    pub fn covering(
+        shape: &'static Tetromino,
+        points: &[(usize, usize)],
+    ) -> Option<ActiveTetromino> {
+        let mut target = points.to_vec();
+        target.sort_unstable();
+        [R0, R1, R2, R3].into_iter().find_map(|rotation| {
+            let mut offsets = shape.points(rotation).to_vec();
+            offsets.sort_unstable();
+            let (x, y) = target.first()?;
+            let candidate = ActiveTetromino {
+                x: *x as i32 - offsets[0].0 as i32,
+                y: *y as i32 - offsets[0].1 as i32,
+                rotation,
+                shape,
+                buffer: BUFFER_HEIGHT,
+            };
+            let mut covered = candidate.as_points();
+            covered.sort_unstable();
+            (covered == target).then_some(candidate)
+        })
+    }
+
+    /// Places the top left corner of the tetromino's box at the given column
+    /// and row of the board
+    pub fn at(shape: &'static Tetromino, x: i32, y: i32, rotation: Rotation) -> ActiveTetromino {
+        ActiveTetromino {
+            x,
+            y,
+            rotation,
+            shape,
+            buffer: BUFFER_HEIGHT,
+        }
+    }
+
+    /// The column, row and orientation as accepted by `at`
+    pub fn position(&self) -> (i32, i32, Rotation) {
+        (self.x, self.y, self.rotation)
+    }
+
+    /// Whether the tetromino doesn't overlap any blocks or walls
+    pub fn fits(&self, board: &Board) -> bool {
+        self.is_move_allowed(self.x, self.y, self.rotation, board)
+    }
+
+    /// The same column and orientation back at the spawn height
+    pub fn lifted(&self) -> ActiveTetromino {
+        ActiveTetromino {
+            y: spawn_row(self.shape, self.buffer),
+            ..self.clone()
+        }
+    }
+
+    pub fn shape(&self) -> &'static Tetromino {
+        self.shape
     }
 
     pub fn get_color(&self) -> Color {
         self.shape.get_color()
     }
 
-    pub fn try_rotate_right(&mut self, board: &[[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT]) {
+    pub fn try_rotate_right(&mut self, board: &Board) -> bool {
         let r = self.rotation.increase();
         if self.is_move_allowed(self.x, self.y, r, board) {
             self.rotation = r;
-        };
+            true
+        } else {
+            false
+        }
     }
 
-    pub fn try_rotate_left(&mut self, board: &[[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT]) {
+    pub fn try_rotate_left(&mut self, board: &Board) -> bool {
         let r = self.rotation.decrease();
         if self.is_move_allowed(self.x, self.y, r, board) {
             self.rotation = r;
-        };
+            true
+        } else {
+            false
+        }
     }
 
-    pub fn try_move_right(&mut self, board: &[[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT]) {
-        if self.is_move_allowed(self.x.wrapping_add(1), self.y, self.rotation, board) {
-            self.x = self.x.wrapping_add(1);
-        };
+    /// Turns the tetromino by 180 degrees, moving it by the first offset of
+    /// the half turn kick table that makes it fit
+    pub fn try_rotate_180(&mut self, board: &Board) -> bool {
+        let r = self.rotation.half_turn();
+        let kick = HALF_TURN_KICKS
+            .iter()
+            .find(|&&(dx, dy)| self.is_move_allowed(self.x + dx, self.y + dy, r, board));
+        if let Some(&(dx, dy)) = kick {
+            self.x += dx;
+            self.y += dy;
+            self.rotation = r;
+            true
+        } else {
+            false
+        }
     }
 
-    pub fn try_move_left(&mut self, board: &[[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT]) {
-        if self.is_move_allowed(self.x.wrapping_sub(1), self.y, self.rotation, board) {
-            self.x = self.x.wrapping_sub(1);
-        };
+    pub fn try_move_right(&mut self, board: &Board) -> bool {
+        if self.is_move_allowed(self.x + 1, self.y, self.rotation, board) {
+            self.x += 1;
+            true
+        } else {
+            false
+        }
     }
 
-    pub fn try_move_down(&mut self, board: &[[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT]) -> bool {
-        if self.is_move_allowed(self.x, self.y.wrapping_add(1), self.rotation, board) {
-            self.y = self.y.wrapping_add(1);
+    pub fn try_move_left(&mut self, board: &Board) -> bool {
+        if self.is_move_allowed(self.x - 1, self.y, self.rotation, board) {
+            self.x -= 1;
             true
         } else {
             false
         }
     }
 
-    fn is_move_allowed(
-        &self,
-        x2: usize,
-        y2: usize,
-        rotation: Rotation,
-        board: &[[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT],
-    ) -> bool {
-        !self
-            .shape
-            .points(rotation)
-            .iter()
-            .map(|&(x1, y1)| (x1.wrapping_add(x2), y1.wrapping_add(y2)))
-            .any(|(x, y)| {
-                board
-                    .get(y)
-                    .and_then(|e| e.get(x))
-                    .map(|e| e.is_some())
-                    .unwrap_or(!HIDDEN_ROWS.contains(&y))
-            })
+    pub fn try_move_down(&mut self, board: &Board) -> bool {
+        if self.is_move_allowed(self.x, self.y + 1, self.rotation, board) {
+            self.y += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Whether the tetromino can't fall any further
+    pub fn is_grounded(&self, board: &Board) -> bool {
+        !self.is_move_allowed(self.x, self.y + 1, self.rotation, board)
+    }
+
+    /// Whether all blocks lie within the vanishing zone above the visible rows
+    pub fn is_above_visible_rows(&self) -> bool {
+        self.cells(self.x, self.y, self.rotation)
+            .all(|(_, y)| y < BUFFER_HEIGHT as i32)
+    }
+
+    fn is_move_allowed(&self, x: i32, y: i32, rotation: Rotation, board: &Board) -> bool {
+        // rows of the vanishing zone above the buffer are off limits
+        let top = (BUFFER_HEIGHT - self.buffer) as i32;
+        self.cells(x, y, rotation).all(|(x, y)| {
+            y >= top
+                && x >= 0
+                && board
+                    .get(y as usize)
+                    .and_then(|row| row.get(x as usize))
+                    .is_some_and(|cell| cell.is_none())
+        })
     }
 }
@@ -0,0 +1,153 @@
+//! What the window or the terminal shows: the menu in front of the game it
+//! starts and continues. The keys go to the menu while it's open and to the
+//! game otherwise, Escape opens the menu during a game.
+
+use image::RgbaImage;
+use piston_window::{
+    Key, MouseButton, UpdateArgs,
+    graphics::{Context, Graphics},
+};
+
+use crate::menu::{Action, Menu, Mode, Setting};
+use crate::piece_set;
+use crate::puzzle::Puzzle;
+use crate::screenshot;
+use crate::settings::Settings;
+use crate::tetris::{Frame, Message, TILE_SIZE, Tetris};
+
+// the modes offered in the menu, the puzzles need a standard board
+fn menu_modes(width: usize, height: usize) -> Vec<Mode> {
+    let mut modes = vec![Mode::Marathon, Mode::Master];
+    if (width, height) == (10, 20) && piece_set::name() == "standard" {
+        modes.push(Mode::Puzzles);
+    }
+    modes.push(Mode::Sandbox);
+    modes
+}
+
+pub struct App {
+    game: Tetris,
+    menu: Menu,
+    quit: bool,
+}
+
+impl App {
+    /// The game with a closed menu offering the modes its board allows
+    pub fn new(game: Tetris) -> App {
+        let (width, height) = game.board_size();
+        let menu = Menu::new(
+            menu_modes(width, height),
+            game.initial_stack_size(),
+            height - 1,
+            Puzzle::builtins().len(),
+        );
+        App {
+            game,
+            menu,
+            quit: false,
+        }
+    }
+
+    pub fn game(&self) -> &Tetris {
+        &self.game
+    }
+
+    pub fn game_mut(&mut self) -> &mut Tetris {
+        &mut self.game
+    }
+
+    /// Shows the title screen, `can_continue` offers to go back to the game
+    pub fn open_menu(&mut self, can_continue: bool) {
+        self.menu.open(can_continue);
+    }
+
+    pub fn menu_open(&self) -> bool {
+        self.menu.is_open()
+    }
+
+    /// Whether the player chose to quit in the menu
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+
+    /// The game stands still while the menu is open
+    pub fn update(&mut self, args: &UpdateArgs) {
+        if !self.menu.is_open() {
+            self.game.update(args);
+        }
+    }
+
+    pub fn key_press(&mut self, key: &Key) {
+        if self.menu.is_open() {
+            let options = self.game.options();
+            match self.menu.key_press(*key, &options) {
+                Some(Action::Start(choice)) => self.game.start(choice),
+                Some(Action::Change(setting)) => self.change_setting(setting),
+                Some(Action::Quit) => self.quit = true,
+                Some(Action::Continue) | None => {}
+            }
+        } else if *key == Key::Escape {
+            self.game.release_controls();
+            self.menu.open(true);
+        } else {
+            self.game.key_press(key);
+        }
+    }
+
+    /// Keys released in the menu don't reach the game, the keys held as it
+    /// opened were let go of then
+    pub fn key_release(&mut self, key: &Key) {
+        if !self.menu.is_open() {
+            self.game.key_release(key);
+        }
+    }
+
+    pub fn mouse_press(&mut self, button: &MouseButton) {
+        if !self.menu.is_open() {
+            self.game.mouse_press(button);
+        }
+    }
+
+    // applies a setting changed in the menu and remembers it for the next start
+    fn change_setting(&mut self, setting: Setting) {
+        let options = self.game.options();
+        match setting {
+            Setting::Particles(on) => self.game.set_effects(on, options.screen_shake),
+            Setting::ScreenShake(on) => self.game.set_effects(options.particles, on),
+            Setting::Patterns(on) => self.game.set_patterns(on),
+            Setting::LineClearDelay(millis) => {
+                self.game.set_line_clear_delay(millis as f64 / 1000.0)
+            }
+        }
+        if let Err(e) = Settings::store(setting.key(), &setting.value()) {
+            self.game.report(Message::Error(format!(
+                "Could not store the setting: {}",
+                e
+            )));
+        }
+    }
+
+    pub fn render<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
+        &mut self,
+        c: &Context,
+        g: &mut G,
+    ) {
+        match self.menu.view(&self.game.options()) {
+            Some(view) => self.game.render_menu(&view, c, g),
+            None => self.game.render(c, g),
+        }
+    }
+
+    /// The game as shown without textures, or the open menu screen
+    pub fn frame(&self) -> Frame {
+        Frame {
+            menu: self.menu.view(&self.game.options()),
+            ..self.game.frame()
+        }
+    }
+
+    /// What is shown as a picture, drawn without a window
+    pub fn screenshot(&self) -> RgbaImage {
+        screenshot::render(&self.frame(), TILE_SIZE as u32)
+    }
+}
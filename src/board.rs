@@ -0,0 +1,64 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::tetromino::Color;
+
+pub const DEFAULT_WIDTH: usize = 10;
+pub const DEFAULT_HEIGHT: usize = 20;
+/// Number of rows of the vanishing zone above the visible ones
+pub const BUFFER_HEIGHT: usize = 20;
+
+/// The rows of the vanishing zone followed by the visible rows, so row
+/// `BUFFER_HEIGHT` is the topmost visible one
+#[derive(Clone)]
+pub struct Board {
+    rows: Vec<Vec<Option<Color>>>,
+}
+
+impl Board {
+    /// An empty board with the given number of columns and visible rows
+    pub fn new(width: usize, height: usize) -> Board {
+        Board {
+            rows: vec![vec![None; width]; BUFFER_HEIGHT + height],
+        }
+    }
+
+    /// An empty board of the same size
+    pub fn cleared(&self) -> Board {
+        Board::new(self.width(), self.height())
+    }
+
+    pub fn width(&self) -> usize {
+        self.rows[0].len()
+    }
+
+    /// Number of visible rows
+    pub fn height(&self) -> usize {
+        self.rows.len() - BUFFER_HEIGHT
+    }
+
+    /// Removes all full rows and lets the rows above fall down.
+    /// Returns the number of removed rows.
+    pub fn clear_lines(&mut self) -> usize {
+        let (width, total) = (self.width(), self.rows.len());
+        self.rows
+            .retain(|row| row.iter().any(|cell| cell.is_none()));
+        let cleared = total - self.rows.len();
+        let empty = vec![None; width];
+        self.rows.splice(0..0, std::iter::repeat_n(empty, cleared));
+        cleared
+    }
+}
+
+impl Deref for Board {
+    type Target = [Vec<Option<Color>>];
+
+    fn deref(&self) -> &Self::Target {
+        &self.rows
+    }
+}
+
+impl DerefMut for Board {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.rows
+    }
+}
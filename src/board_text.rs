@@ -0,0 +1,199 @@
+//! A plain text format for boards: one line per row with `.` for an empty
+//! cell and the initial of its colour for a filled one
+//! (`C`yan, `B`lue, `O`range, `Y`ellow, `L`ime, `P`urple, `R`ed, `G`rey).
+//! Lower case letters mark the falling tetromino and are skipped when parsing.
+//! Rows of the vanishing zone follow above the visible rows.
+
+use crate::active::ActiveTetromino;
+use crate::board::{BUFFER_HEIGHT, Board};
+use crate::tetromino::Color;
+
+fn letter(color: Color) -> char {
+    match color {
+        Color::Cyan => 'C',
+        Color::Blue => 'B',
+        Color::Orange => 'O',
+        Color::Yellow => 'Y',
+        Color::Lime => 'L',
+        Color::Purple => 'P',
+        Color::Red => 'R',
+        Color::Grey => 'G',
+    }
+}
+
+fn color(letter: char) -> Option<Color> {
+    match letter {
+        'C' => Some(Color::Cyan),
+        'B' => Some(Color::Blue),
+        'O' => Some(Color::Orange),
+        'Y' => Some(Color::Yellow),
+        'L' => Some(Color::Lime),
+        'P' => Some(Color::Purple),
+        'R' => Some(Color::Red),
+        'G' => Some(Color::Grey),
+        _ => None,
+    }
+}
+
+/// Prints the visible rows of the board and the rows of the vanishing zone
+/// up to the highest block, including the falling tetromino if given
+pub fn format(board: &Board, active: Option<&ActiveTetromino>) -> String {
+    let mut rows: Vec<Vec<char>> = board
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| cell.map(letter).unwrap_or('.'))
+                .collect()
+        })
+        .collect();
+    if let Some(active) = active {
+        let active_letter = letter(active.get_color()).to_ascii_lowercase();
+        for (x, y) in active.as_points() {
+            if let Some(cell) = rows.get_mut(y).and_then(|row| row.get_mut(x)) {
+                *cell = active_letter;
+            }
+        }
+    }
+    let top = rows
+        .iter()
+        .position(|row| row.iter().any(|&c| c != '.'))
+        .map_or(BUFFER_HEIGHT, |top| top.min(BUFFER_HEIGHT));
+    rows.into_iter()
+        .skip(top)
+        .map(|row| row.into_iter().chain(['\n']).collect::<String>())
+        .collect()
+}
+
+/// Parses a board of the given number of columns and visible rows. The rows
+/// are aligned to the bottom so the empty rows at the top may be left out.
+pub fn parse(text: &str, width: usize, height: usize) -> Result<Board, String> {
+    let rows: Vec<&str> = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    let mut board = Board::new(width, height);
+    if rows.len() > board.len() {
+        return Err(format!("a board has at most {} rows", board.len()));
+    }
+    for (board_row, row) in board.iter_mut().rev().zip(rows.iter().rev()) {
+        if row.chars().count() != width {
+            return Err(format!("row '{}' is not {} cells wide", row, width));
+        }
+        for (cell, c) in board_row.iter_mut().zip(row.chars()) {
+            *cell = match c {
+                '.' => None,
+                c if c.is_ascii_lowercase() && color(c.to_ascii_uppercase()).is_some() => None,
+                c => Some(
+                    color(c).ok_or_else(|| format!("unexpected cell '{}' in row '{}'", c, row))?,
+                ),
+            };
+        }
+    }
+    Ok(board)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Color; 8] = [
+        Color::Cyan,
+        Color::Blue,
+        Color::Orange,
+        Color::Yellow,
+        Color::Lime,
+        Color::Purple,
+        Color::Red,
+        Color::Grey,
+    ];
+
+    #[test]
+    fn round_trips_every_color() {
+        let mut board = Board::new(8, 10);
+        let bottom = board.len() - 1;
+        for (x, &color) in COLORS.iter().enumerate() {
+            board[bottom][x] = Some(color);
+            board[bottom - 1][7 - x] = Some(color);
+        }
+        let text = format(&board, None);
+        assert!(text.ends_with("GRPLYOBC\nCBOYLPRG\n"), "{}", text);
+        assert_eq!(*parse(&text, 8, 10).unwrap(), *board);
+    }
+
+    #[test]
+    fn skips_the_falling_tetromino() {
+        let board = parse("..p.\nGGG.\n", 4, 10).unwrap();
+        let bottom = board.len() - 1;
+        assert!(board[bottom - 1].iter().all(Option::is_none));
+        assert_eq!(board[bottom][..3], [Some(Color::Grey); 3]);
+    }
+
+    #[test]
+    fn rejects_rows_of_the_wrong_width() {
+        let error = parse("GGGG\nGGG\n", 4, 10).err();
+        assert_eq!(error.as_deref(), Some("row 'GGG' is not 4 cells wide"));
+        assert!(parse("GGGGG\n", 4, 10).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_characters() {
+        let error = parse("GXG.\n", 4, 10).err();
+        assert_eq!(error.as_deref(), Some("unexpected cell 'X' in row 'GXG.'"));
+        assert!(parse("..x.\n", 4, 10).is_err());
+    }
+
+    // clears the full rows of a board four cells wide and four rows high
+    fn clear_lines(text: &str) -> (usize, String) {
+        let mut board = parse(text, 4, 4).unwrap();
+        let cleared = board.clear_lines();
+        (cleared, format(&board, None))
+    }
+
+    #[test]
+    fn clears_rows_apart_from_each_other() {
+        let board = "\
+            .R..\n\
+            GGGG\n\
+            .G.G\n\
+            GGGG\n";
+        let expected = "\
+            ....\n\
+            ....\n\
+            .R..\n\
+            .G.G\n";
+        assert_eq!(clear_lines(board), (2, expected.to_string()));
+    }
+
+    #[test]
+    fn clears_rows_of_the_vanishing_zone() {
+        // the two rows above the visible four lie in the vanishing zone
+        let board = "\
+            ..C.\n\
+            CCCC\n\
+            GG.G\n\
+            GGGG\n\
+            .GGG\n\
+            GGGG\n";
+        let expected = "\
+            ....\n\
+            ..C.\n\
+            GG.G\n\
+            .GGG\n";
+        assert_eq!(clear_lines(board), (3, expected.to_string()));
+    }
+
+    #[test]
+    fn leaves_a_board_without_full_rows() {
+        let board = "..C.\nGG.G\n";
+        assert_eq!(clear_lines(board), (0, format!("....\n....\n{}", board)));
+    }
+
+    #[test]
+    fn tolerates_a_missing_or_trailing_newline() {
+        let expected = parse("G...\n.G..\n", 4, 10).unwrap();
+        assert_eq!(*parse("G...\n.G..", 4, 10).unwrap(), *expected);
+        assert_eq!(*parse("G...\n.G..\n\n", 4, 10).unwrap(), *expected);
+        assert_eq!(*parse("G...\r\n.G..\r\n", 4, 10).unwrap(), *expected);
+    }
+}
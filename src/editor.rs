@@ -0,0 +1,138 @@
+use piston_window::{Key, MouseButton};
+
+use crate::board::Board;
+use crate::piece_set;
+use crate::tetromino::{Color, Tetromino};
+
+/// The colours to paint with, selected by the keys 1 to 8
+pub static PALETTE: [Color; 8] = [
+    Color::Cyan,
+    Color::Blue,
+    Color::Orange,
+    Color::Yellow,
+    Color::Lime,
+    Color::Purple,
+    Color::Red,
+    Color::Grey,
+];
+
+static COLOR_KEYS: [Key; 8] = [
+    Key::D1,
+    Key::D2,
+    Key::D3,
+    Key::D4,
+    Key::D5,
+    Key::D6,
+    Key::D7,
+    Key::D8,
+];
+
+// every letter but C adds the piece of that name, if the piece set has one
+static PIECE_KEYS: [(Key, char); 25] = [
+    (Key::A, 'A'),
+    (Key::B, 'B'),
+    (Key::D, 'D'),
+    (Key::E, 'E'),
+    (Key::F, 'F'),
+    (Key::G, 'G'),
+    (Key::H, 'H'),
+    (Key::I, 'I'),
+    (Key::J, 'J'),
+    (Key::K, 'K'),
+    (Key::L, 'L'),
+    (Key::M, 'M'),
+    (Key::N, 'N'),
+    (Key::O, 'O'),
+    (Key::P, 'P'),
+    (Key::Q, 'Q'),
+    (Key::R, 'R'),
+    (Key::S, 'S'),
+    (Key::T, 'T'),
+    (Key::U, 'U'),
+    (Key::V, 'V'),
+    (Key::W, 'W'),
+    (Key::X, 'X'),
+    (Key::Y, 'Y'),
+    (Key::Z, 'Z'),
+];
+
+/// A sandbox to paint a board and choose the upcoming tetrominoes and the
+/// held one before playing from that state
+pub struct Editor {
+    pub board: Board,
+    pub queue: Vec<&'static Tetromino>,
+    pub hold: Option<&'static Tetromino>,
+    pub color: usize,
+    pub cursor: Option<(usize, usize)>,
+    // the content painted while a mouse button is held down
+    brush: Option<Option<Color>>,
+}
+
+impl Editor {
+    pub fn new(
+        board: Board,
+        queue: Vec<&'static Tetromino>,
+        hold: Option<&'static Tetromino>,
+    ) -> Editor {
+        Editor {
+            board,
+            queue,
+            hold,
+            color: PALETTE.len() - 1,
+            cursor: None,
+            brush: None,
+        }
+    }
+
+    pub fn key_press(&mut self, key: &Key) {
+        if let Some(color) = COLOR_KEYS.iter().position(|k| k == key) {
+            self.color = color;
+        } else if let Some(&(_, name)) = PIECE_KEYS.iter().find(|(k, _)| k == key) {
+            self.queue.extend(Tetromino::from_name(name));
+        } else {
+            match key {
+                Key::Backspace => {
+                    self.queue.pop();
+                }
+                Key::C => {
+                    // cycle through no hold piece and all pieces of the set
+                    let shapes = piece_set::shapes();
+                    let index = self
+                        .hold
+                        .and_then(|hold| shapes.iter().position(|s| std::ptr::eq(s, hold)));
+                    self.hold = match index {
+                        None => shapes.first(),
+                        Some(i) => shapes.get(i + 1),
+                    };
+                }
+                Key::Delete => self.board = self.board.cleared(),
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the cursor to the given cell, `None` if it left the board
+    pub fn mouse_move(&mut self, cell: Option<(usize, usize)>) {
+        self.cursor = cell;
+        self.paint();
+    }
+
+    pub fn mouse_press(&mut self, button: &MouseButton) {
+        self.brush = match button {
+            MouseButton::Left => Some(Some(PALETTE[self.color])),
+            MouseButton::Right => Some(None),
+            _ => None,
+        };
+        self.paint();
+    }
+
+    pub fn mouse_release(&mut self) {
+        self.brush = None;
+    }
+
+    fn paint(&mut self) {
+        if let (Some(content), Some((x, y))) = (self.brush, self.cursor) {
+            self.board[y][x] = content;
+        }
+    }
+}
@@ -0,0 +1,212 @@
+//! Eye candy on top of the board: particles trailing hard drops, sparks
+//! where a tetromino locks, bursts for tetrises and T-spins and the board
+//! shaking on impact. Positions are given in tiles of the board, the
+//! effects have no influence on the game.
+
+use piston_window::graphics::{Context, Graphics, Image, Transformed};
+use piston_window::wgpu_graphics::Texture;
+use rand::Rng;
+
+use crate::board::Board;
+
+/// Rows per second squared particles accelerate downwards
+const PARTICLE_GRAVITY: f64 = 20.0;
+/// Seconds the board shakes after an impact
+const SHAKE_TIME: f64 = 0.25;
+
+struct Particle {
+    x: f64,
+    y: f64,
+    vx: f64,
+    vy: f64,
+    // seconds left and in total
+    life: f64,
+    lifetime: f64,
+    color: [f32; 4],
+    size: f64,
+    falls: bool,
+}
+
+#[derive(Default)]
+pub struct Effects {
+    particles: Vec<Particle>,
+    shake_strength: f64,
+    shake_remaining: f64,
+    shake_time: f64,
+    show_particles: bool,
+    show_shake: bool,
+}
+
+impl Effects {
+    pub fn new(show_particles: bool, show_shake: bool) -> Effects {
+        Effects {
+            show_particles,
+            show_shake,
+            ..Default::default()
+        }
+    }
+
+    pub fn shows_particles(&self) -> bool {
+        self.show_particles
+    }
+
+    pub fn shows_shake(&self) -> bool {
+        self.show_shake
+    }
+
+    fn add(&mut self, particle: Particle) {
+        if self.show_particles {
+            self.particles.push(particle);
+        }
+    }
+
+    /// Streaks along the columns a tetromino fell through on a hard drop,
+    /// `points` is where it landed after falling `distance` rows
+    pub fn trail(&mut self, points: &[(usize, usize)], distance: usize, color: [f32; 4]) {
+        let mut rng = rand::rng();
+        for &(x, y) in points {
+            for row in y.saturating_sub(distance)..y {
+                let lifetime = rng.random_range(0.1..0.3);
+                self.add(Particle {
+                    x: x as f64 + rng.random_range(0.2..0.8),
+                    y: row as f64 + rng.random_range(0.0..1.0),
+                    vx: 0.0,
+                    vy: rng.random_range(-2.0..0.0),
+                    life: lifetime,
+                    lifetime,
+                    color,
+                    size: 0.15,
+                    falls: false,
+                });
+            }
+        }
+        self.shake(0.08);
+    }
+
+    /// Sparks flying off the bottom of a tetromino which just locked
+    pub fn sparks(&mut self, points: &[(usize, usize)], color: [f32; 4]) {
+        let mut rng = rand::rng();
+        let bottom = points
+            .iter()
+            .filter(|&&(x, y)| !points.contains(&(x, y + 1)));
+        for &(x, y) in bottom {
+            for _ in 0..3 {
+                let lifetime = rng.random_range(0.2..0.4);
+                self.add(Particle {
+                    x: x as f64 + rng.random_range(0.0..1.0),
+                    y: y as f64 + 1.0,
+                    vx: rng.random_range(-3.0..3.0),
+                    vy: rng.random_range(-6.0..-2.0),
+                    life: lifetime,
+                    lifetime,
+                    color: [1.0, 1.0, 0.8, 1.0],
+                    size: 0.1,
+                    falls: true,
+                });
+            }
+            // a faint puff in the color of the tetromino
+            self.add(Particle {
+                x: x as f64 + 0.5,
+                y: y as f64 + 1.0,
+                vx: 0.0,
+                vy: -0.5,
+                life: 0.2,
+                lifetime: 0.2,
+                color,
+                size: 0.3,
+                falls: false,
+            });
+        }
+    }
+
+    /// Blows the cells of the given full rows apart, for tetrises and T-spins
+    pub fn burst(&mut self, board: &Board, rows: &[usize]) {
+        let mut rng = rand::rng();
+        let center = board.width() as f64 / 2.0;
+        for &y in rows {
+            for (x, cell) in board[y].iter().enumerate() {
+                let Some(color) = cell else { continue };
+                for _ in 0..4 {
+                    let lifetime = rng.random_range(0.4..0.8);
+                    let x = x as f64 + rng.random_range(0.0..1.0);
+                    self.add(Particle {
+                        x,
+                        y: y as f64 + rng.random_range(0.0..1.0),
+                        vx: (x - center) * rng.random_range(1.0..3.0),
+                        vy: rng.random_range(-10.0..-3.0),
+                        life: lifetime,
+                        lifetime,
+                        color: color.as_rgba(),
+                        size: rng.random_range(0.15..0.35),
+                        falls: true,
+                    });
+                }
+            }
+        }
+        self.shake(0.1 * rows.len() as f64);
+    }
+
+    /// Shakes the board by up to `strength` tiles, a stronger shake replaces
+    /// a weaker one
+    pub fn shake(&mut self, strength: f64) {
+        if self.show_shake && (self.shake_remaining <= 0.0 || strength >= self.shake_strength) {
+            self.shake_strength = strength;
+            self.shake_remaining = SHAKE_TIME;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.particles.clear();
+        self.shake_remaining = 0.0;
+    }
+
+    pub fn update(&mut self, dt: f64) {
+        for particle in self.particles.iter_mut() {
+            particle.life -= dt;
+            if particle.falls {
+                particle.vy += PARTICLE_GRAVITY * dt;
+            }
+            particle.x += particle.vx * dt;
+            particle.y += particle.vy * dt;
+        }
+        self.particles.retain(|particle| particle.life > 0.0);
+        self.shake_remaining = (self.shake_remaining - dt).max(0.0);
+        self.shake_time += dt;
+    }
+
+    /// How far the board is moved by the shake, in tiles
+    pub fn offset(&self) -> (f64, f64) {
+        let amount = self.shake_strength * self.shake_remaining / SHAKE_TIME;
+        (
+            amount * (self.shake_time * 97.0).sin(),
+            amount * (self.shake_time * 71.0).cos(),
+        )
+    }
+
+    /// Draws the particles below the row `first_row`, `field` has its origin
+    /// at the top left of the board
+    pub fn render<G: Graphics<Texture = Texture>>(
+        &self,
+        texture: &Texture,
+        tile_size: f64,
+        first_row: usize,
+        field: &Context,
+        g: &mut G,
+    ) {
+        for particle in self.particles.iter().filter(|p| p.y >= first_row as f64) {
+            let [r, gr, b, a] = particle.color;
+            let fade = (particle.life / particle.lifetime) as f32;
+            let size = particle.size * tile_size;
+            Image::new_color([r, gr, b, a * fade])
+                .rect([-size / 2.0, -size / 2.0, size, size])
+                .draw(
+                    texture,
+                    &Default::default(),
+                    field
+                        .trans(particle.x * tile_size, particle.y * tile_size)
+                        .transform,
+                    g,
+                );
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::active::ActiveTetromino;
+use crate::board::Board;
+use crate::tetromino::{Color, Tetromino};
+
+// weights of the well known heuristic by Yiyuan Lee
+const HEIGHT_WEIGHT: f64 = -0.510066;
+const LINES_WEIGHT: f64 = 0.760666;
+const HOLES_WEIGHT: f64 = -0.35663;
+const BUMPINESS_WEIGHT: f64 = -0.184483;
+
+/// A reachable final position of a tetromino
+pub struct Placement {
+    pub points: Vec<(usize, usize)>,
+    pub score: f64,
+    pub stats: BoardStats,
+}
+
+/// The coach's judgement of a locked tetromino
+#[derive(Copy, Clone, Default)]
+pub struct Verdict {
+    pub created_holes: bool,
+    pub created_overhangs: bool,
+    pub raised_height: bool,
+}
+
+impl Verdict {
+    pub fn is_bad(&self) -> bool {
+        self.created_holes || self.created_overhangs || self.raised_height
+    }
+}
+
+/// Metrics describing the shape of a stack
+#[derive(Copy, Clone, Default)]
+pub struct BoardStats {
+    pub holes: usize,
+    pub overhangs: usize,
+    pub max_height: usize,
+    pub aggregate_height: usize,
+    pub bumpiness: usize,
+}
+
+pub fn analyze(board: &Board) -> BoardStats {
+    let mut stats = BoardStats::default();
+    let mut heights = vec![0usize; board.width()];
+    for (x, height) in heights.iter_mut().enumerate() {
+        if let Some(top) = (0..board.len()).find(|&y| board[y][x].is_some()) {
+            *height = board.len() - top;
+            let covered = (top..board.len()).filter(|&y| board[y][x].is_none());
+            stats.holes += covered.count();
+        }
+    }
+    // an overhang is a filled cell with an empty cell directly below
+    for rows in board.windows(2) {
+        let covered = rows[0].iter().zip(rows[1].iter());
+        stats.overhangs += covered.filter(|(a, b)| a.is_some() && b.is_none()).count();
+    }
+    stats.max_height = heights.iter().copied().max().unwrap_or(0);
+    stats.aggregate_height = heights.iter().sum();
+    stats.bumpiness = heights.windows(2).map(|w| w[0].abs_diff(w[1])).sum();
+    stats
+}
+
+/// Locks the points into the board and removes full lines.
+/// Returns the number of cleared lines.
+pub fn place(board: &mut Board, points: &[(usize, usize)], color: Color) -> usize {
+    for &(x, y) in points {
+        if let Some(cell) = board.get_mut(y).and_then(|row| row.get_mut(x)) {
+            *cell = Some(color);
+        }
+    }
+    board.clear_lines()
+}
+
+fn score(stats: &BoardStats, lines: usize) -> f64 {
+    HEIGHT_WEIGHT * stats.aggregate_height as f64
+        + LINES_WEIGHT * lines as f64
+        + HOLES_WEIGHT * stats.holes as f64
+        + BUMPINESS_WEIGHT * stats.bumpiness as f64
+}
+
+/// Enumerates all placements which can be reached from the spawn position
+/// by rotating, shifting and dropping the tetromino. `buffer` is the number
+/// of rows of the vanishing zone it may enter, as in the game.
+pub fn placements(board: &Board, shape: &'static Tetromino, buffer: usize) -> Vec<Placement> {
+    let mut result: Vec<Placement> = Vec::new();
+    let mut rotated = ActiveTetromino::spawn(shape, board.width(), buffer);
+    for _ in 0..4 {
+        let mut shifted = rotated.clone();
+        while shifted.try_move_left(board) {}
+        loop {
+            let mut dropped = shifted.clone();
+            while dropped.try_move_down(board) {}
+            let mut points = dropped.as_points();
+            points.sort_unstable();
+            // placements completely within the vanishing zone lose the game
+            if !dropped.is_above_visible_rows() && !result.iter().any(|p| p.points == points) {
+                let mut after = board.clone();
+                let lines = place(&mut after, &points, shape.get_color());
+                let stats = analyze(&after);
+                result.push(Placement {
+                    points,
+                    score: score(&stats, lines),
+                    stats,
+                });
+            }
+            if !shifted.try_move_right(board) {
+                break;
+            }
+        }
+        if !rotated.try_rotate_right(board) {
+            break;
+        }
+    }
+    result
+}
+
+pub fn best_placement(
+    board: &Board,
+    shape: &'static Tetromino,
+    buffer: usize,
+) -> Option<Placement> {
+    placements(board, shape, buffer)
+        .into_iter()
+        .max_by(|a, b| a.score.total_cmp(&b.score))
+}
+
+/// Judges the placement of `shape` at `points` on the board as it was before
+/// the lock. Raising the stack only counts as a fault if the best placement
+/// would have kept it lower.
+pub fn judge(
+    board: &Board,
+    points: &[(usize, usize)],
+    shape: &'static Tetromino,
+    buffer: usize,
+) -> Verdict {
+    let before = analyze(board);
+    let mut after = board.clone();
+    place(&mut after, points, shape.get_color());
+    let after = analyze(&after);
+    let best_height = best_placement(board, shape, buffer)
+        .map(|p| p.stats.max_height)
+        .unwrap_or(after.max_height);
+    Verdict {
+        created_holes: after.holes > before.holes,
+        created_overhangs: after.overhangs > before.overhangs,
+        raised_height: after.max_height > before.max_height && after.max_height > best_height,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_text;
+    use crate::tetromino::SHAPES;
+
+    #[test]
+    fn passes_a_full_column_only_through_the_vanishing_zone() {
+        let board = board_text::parse(&"..G.......\n".repeat(20), 10, 20).unwrap();
+        let o = SHAPES.iter().find(|shape| shape.get_name() == 'O').unwrap();
+        let left_of_the_wall = |buffer| {
+            placements(&board, o, buffer)
+                .iter()
+                .any(|p| p.points.iter().all(|&(x, _)| x < 2))
+        };
+        assert!(left_of_the_wall(20));
+        assert!(!left_of_the_wall(0));
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::{HashSet, VecDeque};
+
+use crate::active::ActiveTetromino;
+use crate::board::Board;
+
+/// The inputs a player may use to position a tetromino before dropping it.
+/// Holding a move key until the piece hits the wall (DAS) counts as one input.
+#[derive(Copy, Clone)]
+enum Input {
+    MoveLeft,
+    MoveRight,
+    DasLeft,
+    DasRight,
+    RotateRight,
+    RotateLeft,
+    Rotate180,
+}
+
+static INPUTS: [Input; 7] = [
+    Input::MoveLeft,
+    Input::MoveRight,
+    Input::DasLeft,
+    Input::DasRight,
+    Input::RotateRight,
+    Input::RotateLeft,
+    Input::Rotate180,
+];
+
+fn apply(tetromino: &mut ActiveTetromino, input: Input, board: &Board) {
+    match input {
+        Input::MoveLeft => {
+            tetromino.try_move_left(board);
+        }
+        Input::MoveRight => {
+            tetromino.try_move_right(board);
+        }
+        Input::DasLeft => while tetromino.try_move_left(board) {},
+        Input::DasRight => while tetromino.try_move_right(board) {},
+        Input::RotateRight => {
+            tetromino.try_rotate_right(board);
+        }
+        Input::RotateLeft => {
+            tetromino.try_rotate_left(board);
+        }
+        Input::Rotate180 => {
+            tetromino.try_rotate_180(board);
+        }
+    }
+}
+
+fn dropped_points(tetromino: &ActiveTetromino, board: &Board) -> Vec<(usize, usize)> {
+    let mut dropped = tetromino.clone();
+    while dropped.try_move_down(board) {}
+    let mut points = dropped.as_points();
+    points.sort_unstable();
+    points
+}
+
+/// Returns the minimal number of inputs needed to bring a freshly spawned
+/// tetromino into the column and orientation of `locked`, or `None` if the
+/// placement can't be reached by a hard drop from above (e.g. tucks and spins).
+pub fn minimal_inputs(locked: &ActiveTetromino, board: &Board) -> Option<usize> {
+    let mut locked_points = locked.as_points();
+    locked_points.sort_unstable();
+    if dropped_points(&locked.lifted(), board) != locked_points {
+        return None;
+    }
+    // finesse is defined on an empty field
+    let empty = board.cleared();
+    let target = dropped_points(&locked.lifted(), &empty);
+    let spawn = ActiveTetromino::new(locked.shape(), board.width());
+    let mut visited = HashSet::from([spawn.as_points()]);
+    let mut queue = VecDeque::from([(spawn, 0)]);
+    while let Some((tetromino, count)) = queue.pop_front() {
+        if dropped_points(&tetromino, &empty) == target {
+            return Some(count);
+        }
+        for &input in INPUTS.iter() {
+            let mut next = tetromino.clone();
+            apply(&mut next, input, &empty);
+            if visited.insert(next.as_points()) {
+                queue.push_back((next, count + 1));
+            }
+        }
+    }
+    None
+}
+
+/// Finesse statistics of a game
+#[derive(Copy, Clone, Default)]
+pub struct FinesseStats {
+    pub pieces: usize,
+    pub faults: usize,
+    pub extra_inputs: usize,
+    pub last_inputs: usize,
+    pub last_minimum: usize,
+}
+
+impl FinesseStats {
+    pub fn record(&mut self, inputs: usize, minimum: usize) {
+        self.pieces += 1;
+        self.last_inputs = inputs;
+        self.last_minimum = minimum;
+        if inputs > minimum {
+            self.faults += 1;
+            self.extra_inputs += inputs - minimum;
+        }
+    }
+}
@@ -0,0 +1,586 @@
+//! Encoding and decoding of fumen (v115) data, the format of the popular
+//! online board editor. Only the first page of a fumen is used; the upcoming
+//! pieces are stored in the quiz comment `#Q=[hold](current)next`.
+
+use crate::active::ActiveTetromino;
+use crate::board::Board;
+use crate::tetromino::{Color, Tetromino};
+
+const PREFIX: &str = "v115@";
+const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const COMMENT_TABLE: &[u8; 95] = b" !\"#$%&'()*+,-./0123456789:;<=>?@ABCDEFGHIJKLMNOPQRSTUVWXYZ[\\]^_`abcdefghijklmnopqrstuvwxyz{|}~";
+const COMMENT_BASE: u32 = COMMENT_TABLE.len() as u32 + 1;
+pub const FIELD_WIDTH: usize = 10;
+const FIELD_TOP: usize = 23;
+// the field has an extra garbage row below the 23 regular rows
+const FIELD_BLOCKS: usize = (FIELD_TOP + 1) * FIELD_WIDTH;
+const GRAY: u8 = 8;
+
+/// A board together with the active, upcoming and held tetrominoes
+pub struct Position {
+    pub board: Board,
+    pub active: Option<ActiveTetromino>,
+    pub queue: Vec<&'static Tetromino>,
+    pub hold: Option<&'static Tetromino>,
+}
+
+// fumen rotations in the order of their encoding
+#[derive(Copy, Clone, PartialEq)]
+enum Rotation {
+    Reverse,
+    Right,
+    Spawn,
+    Left,
+}
+
+static ROTATIONS: [Rotation; 4] = [
+    Rotation::Reverse,
+    Rotation::Right,
+    Rotation::Spawn,
+    Rotation::Left,
+];
+
+static PIECE_NAMES: [char; 7] = ['I', 'L', 'O', 'Z', 'T', 'J', 'S'];
+
+fn piece_of_name(name: char) -> Option<u8> {
+    PIECE_NAMES
+        .iter()
+        .position(|&n| n == name)
+        .map(|i| i as u8 + 1)
+}
+
+fn piece_of_color(color: Color) -> u8 {
+    match color {
+        Color::Cyan => 1,
+        Color::Orange => 2,
+        Color::Yellow => 3,
+        Color::Red => 4,
+        Color::Purple => 5,
+        Color::Blue => 6,
+        Color::Lime => 7,
+        Color::Grey => GRAY,
+    }
+}
+
+fn shape_of_piece(piece: u8) -> Option<&'static Tetromino> {
+    PIECE_NAMES
+        .get((piece as usize).wrapping_sub(1))
+        .and_then(|&name| Tetromino::from_name(name))
+}
+
+fn color_of_piece(piece: u8) -> Option<Color> {
+    match piece {
+        GRAY => Some(Color::Grey),
+        _ => shape_of_piece(piece).map(|shape| shape.get_color()),
+    }
+}
+
+/// Block offsets around the center of a piece with y pointing upwards
+fn blocks(piece: u8, rotation: Rotation) -> [(i32, i32); 4] {
+    let spawn = match piece {
+        1 => [(0, 0), (-1, 0), (1, 0), (2, 0)],
+        2 => [(0, 0), (-1, 0), (1, 0), (1, 1)],
+        3 => [(0, 0), (1, 0), (0, 1), (1, 1)],
+        4 => [(0, 0), (1, 0), (0, 1), (-1, 1)],
+        5 => [(0, 0), (-1, 0), (1, 0), (0, 1)],
+        6 => [(0, 0), (-1, 0), (1, 0), (-1, 1)],
+        _ => [(0, 0), (-1, 0), (0, 1), (1, 1)],
+    };
+    spawn.map(|(x, y)| match rotation {
+        Rotation::Spawn => (x, y),
+        Rotation::Right => (y, -x),
+        Rotation::Reverse => (-x, -y),
+        Rotation::Left => (-y, x),
+    })
+}
+
+/// Fumen stores O, I, S and Z relative to a different center than the one of
+/// `blocks`. Returns the offset from the latter to the stored one.
+fn center_adjustment(piece: u8, rotation: Rotation) -> (i32, i32) {
+    match (PIECE_NAMES[piece as usize - 1], rotation) {
+        ('O', Rotation::Left) => (-1, 1),
+        ('O', Rotation::Reverse) => (-1, 0),
+        ('O', Rotation::Spawn) => (0, 1),
+        ('I', Rotation::Reverse) => (-1, 0),
+        ('I', Rotation::Left) => (0, 1),
+        ('S', Rotation::Spawn) => (0, 1),
+        ('S', Rotation::Right) => (1, 0),
+        ('Z', Rotation::Spawn) => (0, 1),
+        ('Z', Rotation::Left) => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+// board rows count from the top, fumen rows from the bottom
+fn to_fumen_y(y: usize, board: &Board) -> i32 {
+    board.len() as i32 - 1 - y as i32
+}
+
+fn from_fumen_y(y: i32, board: &Board) -> usize {
+    (board.len() as i32 - 1 - y) as usize
+}
+
+fn field_index(x: usize, y: i32) -> usize {
+    (FIELD_TOP as i32 - y - 1) as usize * FIELD_WIDTH + x
+}
+
+struct Writer {
+    data: String,
+}
+
+impl Writer {
+    fn push(&mut self, mut value: u32, digits: usize) {
+        for _ in 0..digits {
+            self.data.push(TABLE[(value % 64) as usize] as char);
+            value /= 64;
+        }
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn pop(&mut self, digits: usize) -> Result<u32, String> {
+        if self.data.len() < digits {
+            return Err("fumen data ends unexpectedly".to_string());
+        }
+        let mut value = 0;
+        for (i, c) in self.data[..digits].iter().enumerate() {
+            let digit = TABLE
+                .iter()
+                .position(|t| t == c)
+                .ok_or_else(|| format!("invalid fumen character '{}'", *c as char))?;
+            value += (digit as u32) << (6 * i);
+        }
+        self.data = &self.data[digits..];
+        Ok(value)
+    }
+}
+
+// the comment is stored in the form of javascript's `escape`
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            'A'..='Z' | 'a'..='z' | '0'..='9' | '@' | '*' | '_' | '+' | '-' | '.' | '/' => {
+                escaped.push(c)
+            }
+            c if (c as u32) < 256 => escaped.push_str(&format!("%{:02X}", c as u32)),
+            c => {
+                let mut units = [0; 2];
+                for unit in c.encode_utf16(&mut units) {
+                    escaped.push_str(&format!("%u{:04X}", unit));
+                }
+            }
+        }
+    }
+    escaped
+}
+
+fn unescape(text: &str) -> String {
+    let mut units = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        let code = if let Some(hex) = rest.strip_prefix("%u").and_then(|r| r.get(..4)) {
+            u16::from_str_radix(hex, 16).ok().map(|unit| (unit, 6))
+        } else if let Some(hex) = rest.strip_prefix('%').and_then(|r| r.get(..2)) {
+            u16::from_str_radix(hex, 16).ok().map(|unit| (unit, 3))
+        } else {
+            None
+        };
+        let (unit, len) = code.unwrap_or((c as u16, c.len_utf8()));
+        units.push(unit);
+        rest = &rest[len..];
+    }
+    String::from_utf16_lossy(&units)
+}
+
+fn quiz(
+    active: Option<&ActiveTetromino>,
+    queue: &[&'static Tetromino],
+    hold: Option<&'static Tetromino>,
+) -> String {
+    let hold: String = hold.map(Tetromino::get_name).into_iter().collect();
+    let current: String = active.map(|a| a.shape().get_name()).into_iter().collect();
+    let next: String = queue.iter().map(|shape| shape.get_name()).collect();
+    format!("#Q=[{}]({}){}", hold, current, next)
+}
+
+/// Encodes the board, the active tetromino, the upcoming ones and the held one.
+/// Fumen fields are always 10 cells wide.
+pub fn encode(
+    board: &Board,
+    active: Option<&ActiveTetromino>,
+    queue: &[&'static Tetromino],
+    hold: Option<&'static Tetromino>,
+) -> Result<String, String> {
+    if board.width() != FIELD_WIDTH {
+        return Err(format!("fumen needs a board {} cells wide", FIELD_WIDTH));
+    }
+    let pieces = active.map(ActiveTetromino::shape).into_iter();
+    if !pieces
+        .chain(queue.iter().copied())
+        .chain(hold)
+        .all(Tetromino::is_standard)
+    {
+        return Err("fumen only knows the seven standard tetrominoes".to_string());
+    }
+    let mut field = [0u8; FIELD_BLOCKS];
+    // the upper rows of the vanishing zone don't fit into the fumen field
+    for (y, row) in board.iter().enumerate().skip(board.len() - FIELD_TOP) {
+        for (x, cell) in row.iter().enumerate() {
+            if let Some(color) = cell {
+                field[field_index(x, to_fumen_y(y, board))] = piece_of_color(*color);
+            }
+        }
+    }
+    let mut writer = Writer {
+        data: String::new(),
+    };
+    // the field is stored as the run length encoded difference to an empty one
+    let mut start = 0;
+    while start < FIELD_BLOCKS {
+        let diff = field[start] as u32 + 8;
+        let count = field[start..]
+            .iter()
+            .take_while(|&&b| b == field[start])
+            .count();
+        writer.push(diff * FIELD_BLOCKS as u32 + count as u32 - 1, 2);
+        start += count;
+    }
+    if field.iter().all(|&b| b == 0) {
+        // number of following pages with the same field
+        writer.push(0, 1);
+    }
+    let (piece, rotation, coordinate) = active
+        .and_then(|active| {
+            let piece = piece_of_name(active.shape().get_name())?;
+            let points: Vec<(i32, i32)> = active
+                .as_points()
+                .iter()
+                .map(|&(x, y)| (x as i32, to_fumen_y(y, board)))
+                .collect();
+            // on high boards the piece may be above the fumen field
+            if points.iter().any(|&(_, y)| y >= FIELD_TOP as i32) {
+                return None;
+            }
+            let mut sorted = points.clone();
+            sorted.sort_unstable();
+            // the center is one of the blocks
+            let candidates = ROTATIONS
+                .iter()
+                .flat_map(|&rotation| points.iter().map(move |&center| (rotation, center)));
+            candidates.into_iter().find_map(|(rotation, (x, y))| {
+                let offsets = blocks(piece, rotation);
+                let mut covered: Vec<(i32, i32)> =
+                    offsets.iter().map(|&(dx, dy)| (x + dx, y + dy)).collect();
+                covered.sort_unstable();
+                if covered != sorted {
+                    return None;
+                }
+                let (ax, ay) = center_adjustment(piece, rotation);
+                let coordinate = field_index((x + ax) as usize, y + ay);
+                Some((piece as u32, rotation as u32, coordinate as u32))
+            })
+        })
+        .unwrap_or((0, 0, 0));
+    let comment = escape(&quiz(active, queue, hold));
+    // the flags from the highest bit: no lock, comment, colorize, mirror, rise
+    let (rise, mirror, colorize, has_comment, lock) = (0, 0, 1, 1, 1);
+    let flags = ((((1 - lock) * 2 + has_comment) * 2 + colorize) * 2 + mirror) * 2 + rise;
+    writer.push(
+        ((flags * FIELD_BLOCKS as u32 + coordinate) * 4 + rotation) * 8 + piece,
+        3,
+    );
+    writer.push(comment.len() as u32, 2);
+    for chunk in comment.as_bytes().chunks(4) {
+        let value = chunk.iter().rev().fold(0, |value, c| {
+            let index = COMMENT_TABLE.iter().position(|t| t == c).unwrap_or(0);
+            value * COMMENT_BASE + index as u32
+        });
+        writer.push(value, 5);
+    }
+    // a '?' is inserted after the first 42 characters and every 47 afterwards
+    let data = writer.data;
+    let mut result = String::from(PREFIX);
+    result.push_str(&data[..data.len().min(42)]);
+    if data.len() > 42 {
+        for chunk in data.as_bytes()[42..].chunks(47) {
+            result.push('?');
+            result.push_str(std::str::from_utf8(chunk).unwrap());
+        }
+    }
+    Ok(result)
+}
+
+/// Decodes the first page of a fumen string or URL onto a board with the
+/// given number of visible rows
+pub fn decode(text: &str, height: usize) -> Result<Position, String> {
+    let start = text
+        .find(PREFIX)
+        .ok_or("only fumen data of version 115 (v115@...) is supported")?;
+    let data: Vec<u8> = text[start + PREFIX.len()..]
+        .bytes()
+        .filter(|&c| c != b'?' && !c.is_ascii_whitespace())
+        .collect();
+    let mut reader = Reader { data: &data };
+    let mut field = [0u8; FIELD_BLOCKS];
+    let mut index = 0;
+    while index < FIELD_BLOCKS {
+        let value = reader.pop(2)?;
+        let diff = value / FIELD_BLOCKS as u32;
+        let count = (value % FIELD_BLOCKS as u32 + 1) as usize;
+        if index + count > FIELD_BLOCKS || !(8..=16).contains(&diff) {
+            return Err("invalid fumen field".to_string());
+        }
+        for block in field[index..index + count].iter_mut() {
+            *block = (diff - 8) as u8;
+        }
+        index += count;
+        if index == FIELD_BLOCKS && count == FIELD_BLOCKS && diff == 8 {
+            reader.pop(1)?;
+        }
+    }
+    // a garbage row below the field becomes the bottom row, lifting the field
+    let rise = field[FIELD_BLOCKS - FIELD_WIDTH..].iter().any(|&b| b != 0) as i32;
+    let mut board = Board::new(FIELD_WIDTH, height);
+    if board.len() < FIELD_TOP + rise as usize {
+        return Err("the board is too low for the fumen field".to_string());
+    }
+    for fumen_y in -rise..FIELD_TOP as i32 {
+        let y = from_fumen_y(fumen_y + rise, &board);
+        for (x, cell) in board[y].iter_mut().enumerate() {
+            *cell = color_of_piece(field[field_index(x, fumen_y)]);
+        }
+    }
+
+    let mut action = reader.pop(3)?;
+    let piece = (action % 8) as u8;
+    action /= 8;
+    let rotation = ROTATIONS[(action % 4) as usize];
+    action /= 4;
+    let coordinate = (action % FIELD_BLOCKS as u32) as i32;
+    action /= FIELD_BLOCKS as u32;
+    // below it the rise, mirror and colorize flags
+    let has_comment = (action >> 3) & 1 == 1;
+
+    let mut active = None;
+    if piece != 0 {
+        let shape = shape_of_piece(piece).ok_or("gray pieces can't be played")?;
+        let (ax, ay) = center_adjustment(piece, rotation);
+        let x = coordinate % FIELD_WIDTH as i32 - ax;
+        let y = FIELD_TOP as i32 - coordinate / FIELD_WIDTH as i32 - 1 - ay;
+        let points: Vec<(usize, usize)> = blocks(piece, rotation)
+            .iter()
+            .map(|&(dx, dy)| {
+                let y = from_fumen_y(y + dy + rise, &board);
+                ((x + dx) as isize as usize, y)
+            })
+            .collect();
+        active = Some(
+            ActiveTetromino::covering(shape, &points)
+                .ok_or("the fumen piece doesn't fit into the board")?,
+        );
+    }
+
+    let mut queue = Vec::new();
+    let mut hold = None;
+    if has_comment {
+        let len = reader.pop(2)? as usize;
+        let mut escaped = Vec::with_capacity(len);
+        while escaped.len() < len {
+            let mut value = reader.pop(5)?;
+            for _ in 0..4 {
+                escaped.push(COMMENT_TABLE[(value % COMMENT_BASE) as usize % COMMENT_TABLE.len()]);
+                value /= COMMENT_BASE;
+            }
+        }
+        escaped.truncate(len);
+        let comment = unescape(&String::from_utf8_lossy(&escaped));
+        if let Some(quiz) = comment.strip_prefix("#Q=[") {
+            let (held, rest) = quiz.split_once(']').unwrap_or(("", ""));
+            hold = held.chars().find_map(Tetromino::from_name);
+            let (current, next) = match rest.strip_prefix('(') {
+                Some(rest) => rest.split_once(')').unwrap_or((rest, "")),
+                None => ("", rest),
+            };
+            if active.is_none() {
+                queue.extend(current.chars().filter_map(Tetromino::from_name));
+            }
+            queue.extend(next.chars().filter_map(Tetromino::from_name));
+        }
+    }
+    Ok(Position {
+        board,
+        active,
+        queue,
+        hold,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board_text;
+    use crate::tetromino::SHAPES;
+
+    fn names(shapes: &[&'static Tetromino]) -> String {
+        shapes.iter().map(|shape| shape.get_name()).collect()
+    }
+
+    fn sorted(mut points: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+        points.sort_unstable();
+        points
+    }
+
+    // two rows of grey garbage with a hole each and a piece of every color
+    // locked on top of them
+    fn board() -> Board {
+        let mut board = Board::new(FIELD_WIDTH, 20);
+        let bottom = board.len() - 1;
+        for (y, hole) in [(bottom, 3), (bottom - 1, 7)] {
+            for (x, cell) in board[y].iter_mut().enumerate() {
+                if x != hole {
+                    *cell = Some(Color::Grey);
+                }
+            }
+        }
+        for (x, shape) in SHAPES.iter().enumerate() {
+            board[bottom - 2][x] = Some(shape.get_color());
+        }
+        board
+    }
+
+    #[test]
+    fn round_trips_every_piece_in_every_rotation() {
+        let board = board();
+        let queue: Vec<&'static Tetromino> = SHAPES.iter().collect();
+        for shape in SHAPES.iter() {
+            for turns in 0..4 {
+                let mut active = ActiveTetromino::spawn(shape, FIELD_WIDTH, 2);
+                for _ in 0..5 {
+                    assert!(active.try_move_down(&board));
+                }
+                for _ in 0..turns {
+                    assert!(active.try_rotate_right(&board) || shape.get_name() == 'O');
+                }
+                let hold = Some(&SHAPES[(turns + 2) % SHAPES.len()]);
+
+                let fumen = encode(&board, Some(&active), &queue, hold).unwrap();
+                let position = decode(&fumen, 20).unwrap();
+
+                assert_eq!(*position.board, *board, "{} {}", shape.get_name(), turns);
+                let decoded = position.active.unwrap();
+                assert_eq!(decoded.shape().get_name(), shape.get_name());
+                assert_eq!(sorted(decoded.as_points()), sorted(active.as_points()));
+                assert_eq!(names(&position.queue), names(&queue));
+                assert_eq!(
+                    position.hold.map(Tetromino::get_name),
+                    hold.map(Tetromino::get_name)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn round_trips_a_board_without_pieces() {
+        let fumen = encode(&board(), None, &[], None).unwrap();
+        let position = decode(&fumen, 20).unwrap();
+        assert_eq!(*position.board, *board());
+        assert!(position.active.is_none());
+        assert!(position.queue.is_empty());
+        assert!(position.hold.is_none());
+    }
+
+    #[test]
+    fn sets_the_comment_flag_of_the_action() {
+        // an empty field, then no piece with the colorize and comment flags
+        let fumen = encode(&Board::new(FIELD_WIDTH, 20), None, &[], None).unwrap();
+        assert!(fumen.starts_with("v115@vhAAgW"), "{}", fumen);
+    }
+
+    #[test]
+    fn decodes_the_empty_page_of_fumen() {
+        // what fumen.zui.jp exports for a new page: an empty field and no piece
+        let position = decode("https://fumen.zui.jp/?v115@vhAAgH", 20).unwrap();
+        assert_eq!(*position.board, *Board::new(FIELD_WIDTH, 20));
+        assert!(position.active.is_none());
+        assert!(position.queue.is_empty());
+        assert!(position.hold.is_none());
+    }
+
+    // the decoded board four rows high as text, the piece in lower case
+    fn decoded(fumen: &str) -> String {
+        let position = decode(fumen, 4).unwrap();
+        board_text::format(&position.board, position.active.as_ref())
+    }
+
+    // Fumen data encoded as the reference implementation (tetris-fumen) does
+    // it: a locked piece above grey blocks at both ends of the bottom row
+    #[test]
+    fn decodes_a_locked_piece_of_every_shape() {
+        let pages = [
+            (
+                "v115@bhB8FeB8JeVLJ",
+                "..........\n....p.....\n...ppp....\nGG......GG\n",
+            ),
+            (
+                "v115@bhB8FeB8JeSLJ",
+                "..........\n.....o....\n...ooo....\nGG......GG\n",
+            ),
+            (
+                "v115@bhB8FeB8JeWLJ",
+                "..........\n...b......\n...bbb....\nGG......GG\n",
+            ),
+            (
+                "v115@bhB8FeB8JeXGJ",
+                "..........\n....ll....\n...ll.....\nGG......GG\n",
+            ),
+            (
+                "v115@bhB8FeB8JeUGJ",
+                "..........\n...rr.....\n....rr....\nGG......GG\n",
+            ),
+            (
+                "v115@bhB8FeB8JeRLJ",
+                "..........\n..........\n...cccc...\nGG......GG\n",
+            ),
+            (
+                "v115@bhB8FeB8JeTGJ",
+                "..........\n....yy....\n....yy....\nGG......GG\n",
+            ),
+        ];
+        for (fumen, board) in pages {
+            assert_eq!(decoded(fumen), board, "{}", fumen);
+        }
+    }
+
+    #[test]
+    fn decodes_i_and_o_in_every_rotation() {
+        // spawn, right, reverse and left, stored around different centers
+        let vertical = "....c.....\n....c.....\n....c.....\n....c.....\nGG......GG\n";
+        let horizontal = "..........\n..........\n...cccc...\nGG......GG\n";
+        let square = "..........\n....yy....\n....yy....\nGG......GG\n";
+        let pages = [
+            ("v115@bhB8FeB8JeRLJ", horizontal),
+            ("v115@bhB8FeB8JeJBJ", vertical),
+            ("v115@bhB8FeB8JeBLJ", horizontal),
+            ("v115@bhB8FeB8JeZBJ", vertical),
+            ("v115@bhB8FeB8JeTGJ", square),
+            ("v115@bhB8FeB8JeLGJ", square),
+            ("v115@bhB8FeB8JeDGJ", square),
+            ("v115@bhB8FeB8JebGJ", square),
+        ];
+        for (fumen, board) in pages {
+            assert_eq!(decoded(fumen), board, "{}", fumen);
+        }
+    }
+
+    #[test]
+    fn imports_the_garbage_row_at_the_bottom() {
+        // a T upside down on the field above a garbage row with a hole
+        let board = "...ppp....\n....p.....\nGG......GG\nGGGGGGGGG.\n";
+        assert_eq!(decoded("v115@bhB8FeK8AeFGJ"), board);
+    }
+}
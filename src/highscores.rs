@@ -0,0 +1,66 @@
+//! The best results of the endless and the master game, kept in
+//! `high_scores.txt` in the data directory as one `mode: score` per line.
+//! The endless game counts cleared lines and the master game the level
+//! reached. Games started from a given board, puzzles and practice games
+//! don't count.
+
+use std::fs;
+
+use crate::storage;
+
+const HIGH_SCORES_FILE: &str = "high_scores.txt";
+
+/// Scores kept per mode
+pub const KEPT: usize = 5;
+
+pub const MARATHON: &str = "marathon";
+pub const MASTER: &str = "master";
+
+/// The best scores of a mode, highest first
+pub fn high_scores(mode: &str) -> Vec<usize> {
+    let mut scores: Vec<usize> = all()
+        .into_iter()
+        .filter(|(m, _)| m == mode)
+        .map(|(_, score)| score)
+        .collect();
+    scores.sort_unstable_by(|a, b| b.cmp(a));
+    scores
+}
+
+// every score in the file, lines which can't be read are skipped
+fn all() -> Vec<(String, usize)> {
+    storage::data_file(HIGH_SCORES_FILE)
+        .and_then(fs::read_to_string)
+        .map(|text| {
+            text.lines()
+                .filter_map(|line| {
+                    let (mode, score) = line.split_once(':')?;
+                    Some((mode.trim().to_string(), score.trim().parse().ok()?))
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Adds a score if it's among the best of its mode and returns its rank
+/// starting at 1, `None` if it didn't make it
+pub fn add(mode: &str, score: usize) -> std::io::Result<Option<usize>> {
+    let mut scores = high_scores(mode);
+    let rank = scores
+        .iter()
+        .position(|&s| score > s)
+        .unwrap_or(scores.len());
+    if rank >= KEPT || score == 0 {
+        return Ok(None);
+    }
+    scores.insert(rank, score);
+    scores.truncate(KEPT);
+    let mut text: String = all()
+        .into_iter()
+        .filter(|(m, _)| m != mode)
+        .map(|(m, s)| format!("{}: {}\n", m, s))
+        .collect();
+    text.extend(scores.iter().map(|s| format!("{}: {}\n", mode, s)));
+    fs::write(storage::data_file(HIGH_SCORES_FILE)?, text)?;
+    Ok(Some(rank + 1))
+}
@@ -0,0 +1,171 @@
+use piston_window::graphics::character::CharacterCache;
+use piston_window::graphics::{Graphics, Text, Transformed, math::Matrix2d, rectangle};
+
+use crate::tetromino::Color;
+
+// 3x5 glyphs, one byte per row with the leftmost cell in bit 2
+static DIGITS: [[u8; 5]; 10] = [
+    [0b111, 0b101, 0b101, 0b101, 0b111],
+    [0b010, 0b110, 0b010, 0b010, 0b010],
+    [0b111, 0b001, 0b010, 0b100, 0b111],
+    [0b111, 0b001, 0b010, 0b001, 0b111],
+    [0b100, 0b100, 0b111, 0b010, 0b010],
+    [0b111, 0b100, 0b111, 0b001, 0b110],
+    [0b011, 0b100, 0b111, 0b101, 0b111],
+    [0b111, 0b001, 0b010, 0b010, 0b010],
+    [0b010, 0b101, 0b010, 0b101, 0b010],
+    [0b111, 0b101, 0b111, 0b001, 0b001],
+];
+
+static LETTERS: [[u8; 5]; 26] = [
+    [0b010, 0b101, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b110, 0b101, 0b110],
+    [0b011, 0b100, 0b100, 0b100, 0b011],
+    [0b110, 0b101, 0b101, 0b101, 0b110],
+    [0b111, 0b100, 0b110, 0b100, 0b111],
+    [0b111, 0b100, 0b110, 0b100, 0b100],
+    [0b011, 0b100, 0b101, 0b101, 0b011],
+    [0b101, 0b101, 0b111, 0b101, 0b101],
+    [0b111, 0b010, 0b010, 0b010, 0b111],
+    [0b001, 0b001, 0b001, 0b101, 0b010],
+    [0b101, 0b101, 0b110, 0b101, 0b101],
+    [0b100, 0b100, 0b100, 0b100, 0b111],
+    [0b101, 0b111, 0b111, 0b101, 0b101],
+    [0b110, 0b101, 0b101, 0b101, 0b101],
+    [0b010, 0b101, 0b101, 0b101, 0b010],
+    [0b110, 0b101, 0b110, 0b100, 0b100],
+    [0b010, 0b101, 0b101, 0b110, 0b011],
+    [0b110, 0b101, 0b110, 0b101, 0b101],
+    [0b011, 0b100, 0b010, 0b001, 0b110],
+    [0b111, 0b010, 0b010, 0b010, 0b010],
+    [0b101, 0b101, 0b101, 0b101, 0b111],
+    [0b101, 0b101, 0b101, 0b101, 0b010],
+    [0b101, 0b101, 0b111, 0b111, 0b101],
+    [0b101, 0b101, 0b010, 0b101, 0b101],
+    [0b101, 0b101, 0b010, 0b010, 0b010],
+    [0b111, 0b001, 0b010, 0b100, 0b111],
+];
+
+// the glyph of a character, letters in either case; unknown ones are blank
+fn glyph(c: char) -> Option<&'static [u8; 5]> {
+    static SYMBOLS: [(char, [u8; 5]); 5] = [
+        ('<', [0b001, 0b010, 0b100, 0b010, 0b001]),
+        ('>', [0b100, 0b010, 0b001, 0b010, 0b100]),
+        (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+        ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+        ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    ];
+    match c {
+        '0'..='9' => Some(&DIGITS[c as usize - '0' as usize]),
+        'a'..='z' | 'A'..='Z' => Some(&LETTERS[c.to_ascii_uppercase() as usize - 'A' as usize]),
+        _ => SYMBOLS
+            .iter()
+            .find(|(symbol, _)| *symbol == c)
+            .map(|(_, glyph)| glyph),
+    }
+}
+
+// 5x5 patterns telling the block colors apart without seeing them, in the
+// order of `Color` with the leftmost cell in bit 4
+static PATTERNS: [[u8; 5]; 8] = [
+    // cyan: horizontal stripes
+    [0b00000, 0b11111, 0b00000, 0b11111, 0b00000],
+    // blue: vertical stripes
+    [0b01010, 0b01010, 0b01010, 0b01010, 0b01010],
+    // orange: a rising diagonal
+    [0b00001, 0b00010, 0b00100, 0b01000, 0b10000],
+    // yellow: a ring
+    [0b00000, 0b01110, 0b01010, 0b01110, 0b00000],
+    // lime: a falling diagonal
+    [0b10000, 0b01000, 0b00100, 0b00010, 0b00001],
+    // purple: a plus
+    [0b00100, 0b00100, 0b11111, 0b00100, 0b00100],
+    // red: a cross
+    [0b10001, 0b01010, 0b00100, 0b01010, 0b10001],
+    // grey: plain
+    [0b00000, 0b00000, 0b00000, 0b00000, 0b00000],
+];
+
+/// Draws the pattern of a block color over a block of the given size with
+/// its top left corner at the origin of `transform`
+pub fn draw_pattern<G: Graphics>(color: Color, size: f64, transform: Matrix2d, g: &mut G) {
+    // the pattern covers the middle 60% of the block
+    let cell = size * 0.12;
+    let transform = transform.trans(size * 0.2, size * 0.2);
+    for (y, row) in PATTERNS[color as usize].iter().enumerate() {
+        for x in (0..5).filter(|x| row & (0b10000 >> x) != 0) {
+            rectangle(
+                [0.0, 0.0, 0.0, 0.5],
+                [x as f64 * cell, y as f64 * cell, cell, cell],
+                transform,
+                g,
+            );
+        }
+    }
+}
+
+/// The filled cells of a text written with the 3x5 glyphs, each followed
+/// by one cell of spacing
+pub fn text_cells(text: &str) -> Vec<(usize, usize)> {
+    text.chars()
+        .enumerate()
+        .filter_map(|(i, c)| glyph(c).map(|glyph| (i, glyph)))
+        .flat_map(|(i, glyph)| {
+            glyph.iter().enumerate().flat_map(move |(y, row)| {
+                (0..3)
+                    .filter(move |x| row & (0b100 >> x) != 0)
+                    .map(move |x| (4 * i + x, y))
+            })
+        })
+        .collect()
+}
+
+pub fn number_cells(number: usize) -> Vec<(usize, usize)> {
+    text_cells(&number.to_string())
+}
+
+/// Draws a number with its first digit at the origin of `transform`, see
+/// `draw_text`
+pub fn draw_number<G: Graphics, C: CharacterCache<Texture = G::Texture>>(
+    number: usize,
+    color: [f32; 4],
+    cell: f64,
+    transform: Matrix2d,
+    font: Option<&mut C>,
+    g: &mut G,
+) {
+    draw_text(&number.to_string(), color, cell, transform, font, g);
+}
+
+/// Draws a text with its first character at the origin of `transform`.
+/// Each glyph is 3x5 cells followed by one cell of spacing. With a font the
+/// text is written in it at about the same height instead.
+pub fn draw_text<G: Graphics, C: CharacterCache<Texture = G::Texture>>(
+    text: &str,
+    color: [f32; 4],
+    cell: f64,
+    transform: Matrix2d,
+    font: Option<&mut C>,
+    g: &mut G,
+) {
+    if let Some(font) = font {
+        // text is placed on its baseline, the digits of a font take about
+        // 70% of its size
+        let style = Text::new_color(color, (5.0 * cell / 0.7) as u32);
+        let transform = transform.trans(0.0, 5.0 * cell);
+        if style
+            .draw(text, font, &Default::default(), transform, g)
+            .is_ok()
+        {
+            return;
+        }
+    }
+    for (x, y) in text_cells(text) {
+        rectangle(
+            color,
+            [x as f64 * cell, y as f64 * cell, cell, cell],
+            transform,
+            g,
+        );
+    }
+}
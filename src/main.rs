@@ -1,19 +1,44 @@
 use clap::Parser;
-use piston_window::wgpu_graphics::{Texture, TextureSettings};
 use piston_window::{
-    Button, PistonWindow, PressEvent, ReleaseEvent, UpdateEvent, WindowSettings, graphics::clear,
+    Button, HatState, Key, MouseCursorEvent, PistonWindow, PressEvent, ReleaseEvent, ResizeEvent,
+    UpdateEvent, Window, WindowSettings, graphics::clear,
 };
+#[cfg(feature = "music")]
 use sdl2::mixer;
+use std::path::{Path, PathBuf};
+use winit::window::Fullscreen;
 
 mod active;
+mod app;
+mod board;
+mod board_text;
+mod editor;
+mod effects;
+mod evaluator;
+mod finesse;
+mod fumen;
+mod highscores;
+mod hud;
+mod menu;
+mod piece_set;
+mod puzzle;
+mod replay;
+mod savegame;
+mod screenshot;
+mod settings;
+mod speed;
+mod storage;
 mod tetris;
 mod tetromino;
+mod theme;
+#[cfg(unix)]
+mod tui;
 
 // Embedded assets (included in the binary)
-const BLOCK_PNG: &[u8] = include_bytes!("../bin/assets/block.png");
 // gravitationalWaves by airtone (c)
 // copyright 2016 Licensed under a Creative Commons Attribution Noncommercial  (3.0) license.
 // http://dig.ccmixter.org/files/airtone/55021
+#[cfg(feature = "music")]
 const WAVES_OGG: &[u8] = include_bytes!("../bin/assets/airtone-gravitationalWaves.ogg");
 
 #[derive(Parser)]
@@ -27,9 +52,128 @@ struct CliArgs {
     #[arg(short = 'o', long)]
     music_off: bool,
 
-    /// Minified rendering for screens < 600x800
+    /// Starts with a window of half the usual size instead of the remembered one
     #[arg(short = 'm', long)]
     mini: bool,
+
+    /// Starts in fullscreen mode (toggle with F11)
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Plays in the terminal instead of a window, without music
+    #[arg(long, conflicts_with_all = ["mini", "fullscreen", "editor"])]
+    tui: bool,
+
+    /// Saves a picture of the starting position as PNG instead of playing, no window is opened
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tui", "mini", "fullscreen"])]
+    screenshot: Option<PathBuf>,
+
+    /// Renders a replay to an animated GIF or numbered PNG frames instead of playing
+    #[arg(long, value_name = "REPLAY", conflicts_with_all = ["tui", "screenshot", "puzzle", "fumen", "board_file", "editor"])]
+    export_replay: Option<PathBuf>,
+
+    /// Where to render the replay: a GIF file or a directory for PNG frames
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value = "replay.gif",
+        requires = "export_replay"
+    )]
+    output: PathBuf,
+
+    /// Frames per second of the rendered replay
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..=60), requires = "export_replay")]
+    fps: u32,
+
+    /// Size of the rendered replay relative to the window, 1 draws blocks of 40 pixels
+    #[arg(long, default_value_t = 0.5, requires = "export_replay")]
+    scale: f64,
+
+    /// Outlines the best placement of the current piece (toggle with F2)
+    #[arg(long)]
+    hint: bool,
+
+    /// Flags placements which create holes or overhangs (toggle with F3)
+    #[arg(long)]
+    coach: bool,
+
+    /// Counts the inputs of every placement and reports finesse faults
+    #[arg(long)]
+    finesse: bool,
+
+    /// Practice mode: Z takes back placements and Y places them again
+    #[arg(long)]
+    practice: bool,
+
+    /// Plays a puzzle given by the number or name of a built-in puzzle or by a puzzle file
+    #[arg(long, value_name = "PUZZLE")]
+    puzzle: Option<String>,
+
+    /// Lists the built-in puzzles
+    #[arg(long)]
+    list_puzzles: bool,
+
+    /// Starts from the position of a fumen string (v115@...)
+    #[arg(long, value_name = "FUMEN")]
+    fumen: Option<String>,
+
+    /// Starts on the board described in a text file
+    #[arg(long, value_name = "FILE")]
+    board_file: Option<PathBuf>,
+
+    /// Opens the sandbox editor right away
+    #[arg(long)]
+    editor: bool,
+
+    /// Rows of the vanishing zone above the board the falling piece may enter, below two rows pieces spawn within the board
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(0..=20))]
+    buffer: u8,
+
+    /// Shows the lowest row of the vanishing zone as a half row above the board
+    #[arg(long)]
+    partial_row: bool,
+
+    /// Number of columns of the board
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u8).range(4..=20), conflicts_with = "puzzle")]
+    width: u8,
+
+    /// Number of visible rows of the board
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u8).range(10..=40), conflicts_with = "puzzle")]
+    height: u8,
+
+    /// Plays with the pieces of a built-in set (standard, pentomino or big) or of a piece set file
+    #[arg(long, value_name = "SET", default_value = "standard", conflicts_with_all = ["puzzle", "fumen"])]
+    pieces: String,
+
+    /// Arcade master mode: the level rises up to 999 with every piece and line, the gravity up to 20G
+    #[arg(long, conflicts_with = "puzzle")]
+    master: bool,
+
+    /// Simulation steps per second, the game runs at the same speed at any frame rate
+    #[arg(long, value_name = "HZ", default_value_t = 60, value_parser = clap::value_parser!(u32).range(30..=1000))]
+    tick_rate: u32,
+
+    /// Starts a new game instead of resuming the one saved when the window closed
+    #[arg(long)]
+    new_game: bool,
+}
+
+/// Picks a built-in puzzle by its number or name or loads a puzzle file.
+/// Returns the puzzles to play through and the index of the first one.
+fn select_puzzle(
+    builtins: Vec<puzzle::Puzzle>,
+    name: &str,
+) -> Result<(Vec<puzzle::Puzzle>, usize), String> {
+    let index = name.parse::<usize>().ok().and_then(|n| n.checked_sub(1));
+    if let Some(index) = index.filter(|&i| i < builtins.len()).or_else(|| {
+        builtins
+            .iter()
+            .position(|p| p.name.eq_ignore_ascii_case(name))
+    }) {
+        Ok((builtins, index))
+    } else {
+        Ok((vec![puzzle::Puzzle::load(Path::new(name))?], 0))
+    }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -37,75 +181,371 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         initial_stack_size,
         music_off,
         mini,
+        fullscreen,
+        tui,
+        screenshot,
+        export_replay,
+        output,
+        fps,
+        scale,
+        hint,
+        coach,
+        finesse,
+        practice,
+        puzzle,
+        list_puzzles,
+        fumen,
+        board_file,
+        editor,
+        new_game,
+        buffer,
+        partial_row,
+        width,
+        height,
+        pieces,
+        master,
+        tick_rate,
     } = CliArgs::parse();
-    let (width, height) = (tetris::WINDOW_WIDTH, tetris::WINDOW_HEIGHT);
-    let (width, height) = if mini {
-        (width / 2, height / 2)
+    // a replay is rendered with the pieces it was played with
+    let replay = export_replay
+        .as_deref()
+        .map(replay::Replay::load)
+        .transpose()?;
+    let pieces = replay
+        .as_ref()
+        .map_or(pieces, |replay| replay.piece_set.clone());
+    let (board_width, board_height) = (width as usize, height as usize);
+    let piece_set = piece_set::PieceSet::by_name(&pieces)?;
+    match &replay {
+        Some(replay) => piece_set.check(replay.width, replay.height, replay.buffer)?,
+        None => piece_set.check(board_width, board_height, buffer as usize)?,
+    }
+    piece_set::select(piece_set)?;
+    let resume = !(new_game
+        || editor
+        || master
+        || puzzle.is_some()
+        || fumen.is_some()
+        || board_file.is_some());
+    let builtin_puzzles = puzzle::Puzzle::builtins();
+    if list_puzzles {
+        let solved = puzzle::solved_puzzles();
+        for (i, puzzle) in builtin_puzzles.iter().enumerate() {
+            let mark = if solved.contains(&puzzle.name) {
+                "x"
+            } else {
+                " "
+            };
+            println!("[{}] {} {}", mark, i + 1, puzzle.name);
+        }
+        return Ok(());
+    }
+    if fumen.is_some() && board_width != fumen::FIELD_WIDTH {
+        return Err(format!("fumen boards are {} columns wide", fumen::FIELD_WIDTH).into());
+    }
+    let position = fumen
+        .map(|data| fumen::decode(&data, board_height))
+        .transpose()?;
+    let board = match board_file {
+        Some(path) => {
+            let text = std::fs::read_to_string(&path)?;
+            Some(
+                board_text::parse(&text, board_width, board_height)
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+            )
+        }
+        None => None,
+    };
+    let puzzles = match puzzle {
+        Some(name) => Some(select_puzzle(builtin_puzzles, &name)?),
+        None => None,
+    };
+    let settings = settings::Settings::load().unwrap_or_else(|e| {
+        eprintln!("Could not load the settings: {}", e);
+        settings::Settings::default()
+    });
+    let size = match settings.window_size {
+        Some(size) if !mini => size,
+        _ => {
+            let scale = if mini { 0.5 } else { 1.0 };
+            tetris::window_size(board_width, board_height, scale, partial_row)
+        }
+    };
+    let window: Option<PistonWindow> = if tui || screenshot.is_some() || replay.is_some() {
+        None
     } else {
-        (width, height)
+        Some(
+            WindowSettings::new("Rusty Tetris", size)
+                .exit_on_esc(false)
+                .resizable(true)
+                .fullscreen(fullscreen)
+                .build()?,
+        )
     };
-    let mut window: PistonWindow = WindowSettings::new("Rusty Tetris", [width, height])
-        .exit_on_esc(true)
-        .build()?;
-
-    let basic_block = {
-        let img = image::load_from_memory(BLOCK_PNG)?.to_rgba8();
-        Texture::from_image(
+    let mut theme = theme::Theme::by_name(&settings.theme).unwrap_or_else(|e| {
+        eprintln!("Could not load the theme: {}", e);
+        theme::Theme::default()
+    });
+    if let Some(palette) = settings.palette.as_ref()
+        && let Err(e) = theme.use_palette(palette)
+    {
+        eprintln!("Could not use the palette: {}", e);
+    }
+    let skin = match window.as_ref() {
+        Some(window) => Some(theme::Skin::new(
+            &theme,
             &mut window.create_texture_context(),
-            &img,
-            &TextureSettings::new(),
-        )
-        .map_err(|e| e.to_string())?
+        )?),
+        None => None,
     };
+    theme::select(theme)?;
 
-    let mut game = tetris::Tetris::new(
-        if mini { 0.5 } else { 1.0 },
-        basic_block,
-        initial_stack_size,
-    );
-
-    let sdl;
-    let _audio;
-    let _mixer;
-    let waves;
-    if !music_off {
-        sdl = sdl2::init()?;
-        _audio = sdl.audio()?;
-        _mixer = mixer::init(mixer::InitFlag::OGG);
-        mixer::open_audio(
-            mixer::DEFAULT_FREQUENCY,
-            mixer::DEFAULT_FORMAT,
-            mixer::DEFAULT_CHANNELS,
-            1024,
-        )?;
-        mixer::allocate_channels(16);
-        waves = mixer::Music::from_static_bytes(WAVES_OGG)?;
-        let vol = (0.4_f64 * mixer::MAX_VOLUME as f64) as i32;
-        mixer::Music::set_volume(vol);
-        waves.play(-1)?;
+    if let Some(replay) = replay {
+        if !(scale > 0.0 && scale <= 4.0) {
+            return Err("the scale is between 0 and 4".into());
+        }
+        let mut game = replay.start(None);
+        let tile = ((tetris::TILE_SIZE * scale).round() as u32).max(1);
+        let frames = replay.export(&mut game, &output, fps, tile)?;
+        println!("Rendered {} frames to {}", frames, output.display());
+        return Ok(());
+    }
+
+    let mut game = tetris::Tetris::new(skin, initial_stack_size, board_width, board_height);
+    game.set_rotate_180_key(settings.rotate_180);
+    game.set_line_clear_delay(settings.line_clear_delay);
+    game.set_buffer(buffer as usize);
+    game.set_partial_row(partial_row);
+    game.set_practice(practice);
+    game.set_master(master);
+    game.set_tick_rate(tick_rate);
+    game.set_effects(settings.particles, settings.screen_shake);
+    game.set_patterns(settings.patterns);
+    game.set_hint(hint);
+    game.set_coach(coach);
+    game.set_finesse(finesse);
+    let replayable = !(editor || puzzles.is_some() || board.is_some() || position.is_some());
+    // the title menu comes up unless the game to play was given
+    let show_menu = replayable && !master && screenshot.is_none();
+    if let Some((puzzles, index)) = puzzles {
+        game.load_puzzles(puzzles, index);
     }
+    if let Some(board) = board {
+        game.load_board(board);
+    }
+    if let Some(position) = position {
+        game.load_position(position)?;
+    }
+    if editor {
+        game.open_editor();
+    }
+    let mut resumed = false;
+    if resume {
+        match savegame::SavedGame::load() {
+            Ok(Some(saved)) => match game.resume(saved) {
+                Ok(()) => resumed = true,
+                Err(e) => eprintln!("Could not resume the saved game: {}", e),
+            },
+            Ok(None) => {}
+            Err(e) => eprintln!("Could not resume the saved game: {}", e),
+        }
+    }
+    // a new endless game is recorded, the others can't be replayed
+    if replayable && !resumed {
+        game.start_recording();
+    }
+    let mut app = app::App::new(game);
+    if show_menu {
+        app.open_menu(resumed);
+    }
+
+    if let Some(path) = screenshot {
+        app.screenshot()
+            .save(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        return Ok(());
+    }
+
+    match window {
+        Some(window) => play_in_window(window, &mut app, music_off, size, settings.window_size)?,
+        #[cfg(unix)]
+        None => tui::run(&mut app)?,
+        #[cfg(not(unix))]
+        None => return Err("the terminal mode is only available on Unix".into()),
+    }
+
+    if let Err(e) = app.game().save() {
+        eprintln!("Could not save the game: {}", e);
+    }
+    app.game_mut().store_replay();
+    for message in app.game_mut().take_messages() {
+        message.print();
+    }
+
+    // Avoid potential destructor-time crashes in some EGL/driver stacks by
+    // exiting immediately (bypasses running global destructors that can hit
+    // driver bugs during cleanup). This is safer for a short-lived app.
+    std::process::exit(0);
+}
+
+// the key a gamepad button stands for, numbered like SDL's game controllers
+fn controller_key(button: u8) -> Option<Key> {
+    match button {
+        // A drops, B and X rotate and Y and the shoulder buttons hold
+        0 => Some(Key::Space),
+        1 => Some(Key::E),
+        2 => Some(Key::Q),
+        3 | 9 | 10 => Some(Key::C),
+        // Start opens the menu
+        6 => Some(Key::Escape),
+        11 => Some(Key::Up),
+        12 => Some(Key::Down),
+        13 => Some(Key::Left),
+        14 => Some(Key::Right),
+        _ => None,
+    }
+}
 
+// the key pressed with a gamepad button or d-pad direction, moving the d-pad
+// releases the key of its previous direction
+fn gamepad_key(button: &Button, held_hat: &mut Option<Key>, app: &mut app::App) -> Option<Key> {
+    match button {
+        Button::Controller(button) => controller_key(button.button),
+        Button::Hat(hat) => {
+            if let Some(key) = held_hat.take() {
+                app.key_release(&key);
+            }
+            *held_hat = match hat.state {
+                HatState::Up => Some(Key::Up),
+                HatState::Down => Some(Key::Down),
+                HatState::Left => Some(Key::Left),
+                HatState::Right => Some(Key::Right),
+                _ => None,
+            };
+            *held_hat
+        }
+        _ => None,
+    }
+}
+
+/// SDL's audio and the music looping on it, the music stops when dropped
+#[cfg(feature = "music")]
+struct Music {
+    _sdl: sdl2::Sdl,
+    _audio: sdl2::AudioSubsystem,
+    _mixer: Result<mixer::Sdl2MixerContext, String>,
+    _waves: mixer::Music<'static>,
+}
+
+#[cfg(feature = "music")]
+fn play_music() -> Result<Music, String> {
+    let sdl = sdl2::init()?;
+    let audio = sdl.audio()?;
+    let mixer_context = mixer::init(mixer::InitFlag::OGG);
+    mixer::open_audio(
+        mixer::DEFAULT_FREQUENCY,
+        mixer::DEFAULT_FORMAT,
+        mixer::DEFAULT_CHANNELS,
+        1024,
+    )?;
+    mixer::allocate_channels(16);
+    let waves = mixer::Music::from_static_bytes(WAVES_OGG)?;
+    let vol = (0.4_f64 * mixer::MAX_VOLUME as f64) as i32;
+    mixer::Music::set_volume(vol);
+    waves.play(-1)?;
+    Ok(Music {
+        _sdl: sdl,
+        _audio: audio,
+        _mixer: mixer_context,
+        _waves: waves,
+    })
+}
+
+/// Plays in the piston window with music until the window is closed and
+/// remembers the size the window had
+fn play_in_window(
+    mut window: PistonWindow,
+    app: &mut app::App,
+    music_off: bool,
+    size: [u32; 2],
+    remembered_size: Option<[u32; 2]>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let window_size = window.size();
+    app.game_mut()
+        .resize([window_size.width, window_size.height]);
+    // the size to remember, fullscreen doesn't change it
+    let mut windowed_size = size;
+
+    // the music plays as long as the window is open
+    #[cfg(feature = "music")]
+    let _music = if music_off { None } else { Some(play_music()?) };
+    #[cfg(not(feature = "music"))]
+    let _ = music_off;
+
+    // the direction the d-pad is held in, it reports no releases
+    let mut held_hat: Option<Key> = None;
     while let Some(e) = window.next() {
         window.draw_2d(&e, |c, gl, _| {
-            clear([1.0; 4], gl);
-            game.render(&c, gl);
+            clear(theme::background(), gl);
+            app.render(&c, gl);
         });
 
         if let Some(uargs) = e.update_args() {
-            game.update(&uargs);
+            app.update(&uargs);
         }
 
-        if let Some(Button::Keyboard(key)) = e.press_args() {
-            game.key_press(&key);
+        if let Some(args) = e.resize_args() {
+            app.game_mut().resize(args.window_size);
+            if window.window.get_window_ref().fullscreen().is_none() {
+                windowed_size = args.window_size.map(|n| n.round() as u32);
+            }
         }
 
-        if let Some(Button::Keyboard(key)) = e.release_args() {
-            game.key_release(&key);
+        if let Some(pos) = e.mouse_cursor_args() {
+            app.game_mut().mouse_move(pos);
+        }
+
+        match e.press_args() {
+            Some(Button::Keyboard(Key::F11)) => {
+                let native = window.window.get_window_ref();
+                let fullscreen = match native.fullscreen() {
+                    Some(_) => None,
+                    None => Some(Fullscreen::Borderless(None)),
+                };
+                native.set_fullscreen(fullscreen);
+            }
+            Some(Button::Keyboard(key)) => app.key_press(&key),
+            Some(Button::Mouse(button)) => app.mouse_press(&button),
+            Some(button) => {
+                if let Some(key) = gamepad_key(&button, &mut held_hat, app) {
+                    app.key_press(&key);
+                }
+            }
+            None => {}
+        }
+        if app.quit_requested() {
+            window.set_should_close(true);
+        }
+        for message in app.game_mut().take_messages() {
+            message.print();
+        }
+
+        match e.release_args() {
+            Some(Button::Keyboard(key)) => app.key_release(&key),
+            Some(Button::Mouse(button)) => app.game_mut().mouse_release(&button),
+            Some(Button::Controller(button)) => {
+                if let Some(key) = controller_key(button.button) {
+                    app.key_release(&key);
+                }
+            }
+            _ => {}
         }
     }
 
-    // Avoid potential destructor-time crashes in some EGL/driver stacks by
-    // exiting immediately (bypasses running global destructors that can hit
-    // driver bugs during cleanup). This is safer for a short-lived app.
-    std::process::exit(0);
+    if remembered_size != Some(windowed_size)
+        && let Err(e) = settings::Settings::store_window_size(windowed_size)
+    {
+        eprintln!("Could not remember the window size: {}", e);
+    }
+    Ok(())
 }
@@ -0,0 +1,465 @@
+//! The title screen shown at start and when Escape is pressed during a game,
+//! and the screens opened from it. The screens are kept on a stack above the
+//! game: the top one gets the keys and is shown instead of the board, going
+//! back returns to the one below and leaving the title screen to the game.
+//! The menu is navigated with the arrow keys (or WASD), Enter or Space
+//! chooses and Escape or Backspace goes back.
+
+use std::collections::HashMap;
+
+use piston_window::Key;
+
+use crate::highscores;
+use crate::settings::MAX_LINE_CLEAR_DELAY;
+use crate::tetromino::Color;
+
+/// Milliseconds the line clear delay changes by
+const LINE_CLEAR_DELAY_STEP: usize = 50;
+
+/// The highest level the endless game may start at, each level is one step
+/// of the speed up
+const MAX_LEVEL: usize = 20;
+
+/// The master game starts at a level of this multiple
+const MASTER_LEVEL_STEP: usize = 100;
+const MAX_MASTER_LEVEL: usize = 900;
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Mode {
+    Marathon,
+    Master,
+    Puzzles,
+    Sandbox,
+}
+
+impl Mode {
+    fn name(self) -> &'static str {
+        match self {
+            Mode::Marathon => "MARATHON",
+            Mode::Master => "MASTER",
+            Mode::Puzzles => "PUZZLES",
+            Mode::Sandbox => "SANDBOX",
+        }
+    }
+
+    // the level chosen at first, the master level counts from zero
+    fn first_level(self) -> usize {
+        match self {
+            Mode::Marathon | Mode::Puzzles => 1,
+            Mode::Master | Mode::Sandbox => 0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Screen {
+    Title,
+    Settings,
+    HighScores,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Item {
+    Continue,
+    Play,
+    Mode,
+    Level,
+    Stack,
+    Settings,
+    HighScores,
+    Quit,
+    Particles,
+    ScreenShake,
+    Patterns,
+    LineClearDelay,
+    Back,
+}
+
+/// The game a player chose to start
+#[derive(Copy, Clone)]
+pub struct Choice {
+    pub mode: Mode,
+    /// The level of the endless or master game or the number of the puzzle
+    pub level: usize,
+    pub initial_stack: usize,
+}
+
+/// The settings which can be changed in the menu
+#[derive(Copy, Clone)]
+pub struct Options {
+    pub particles: bool,
+    pub screen_shake: bool,
+    pub patterns: bool,
+    /// Milliseconds
+    pub line_clear_delay: u32,
+}
+
+/// A changed setting with its line in the settings file
+#[derive(Copy, Clone)]
+pub enum Setting {
+    Particles(bool),
+    ScreenShake(bool),
+    Patterns(bool),
+    LineClearDelay(u32),
+}
+
+impl Setting {
+    pub fn key(self) -> &'static str {
+        match self {
+            Setting::Particles(_) => "particles",
+            Setting::ScreenShake(_) => "screen shake",
+            Setting::Patterns(_) => "patterns",
+            Setting::LineClearDelay(_) => "line clear delay",
+        }
+    }
+
+    pub fn value(self) -> String {
+        match self {
+            Setting::Particles(on) | Setting::ScreenShake(on) | Setting::Patterns(on) => {
+                switch(on).to_lowercase()
+            }
+            Setting::LineClearDelay(millis) => millis.to_string(),
+        }
+    }
+}
+
+/// What the game should do after a key press in the menu
+pub enum Action {
+    Start(Choice),
+    Continue,
+    Change(Setting),
+    Quit,
+}
+
+/// What a screen shows, `selected` is the index of the chosen line
+pub struct View {
+    pub title: &'static str,
+    pub lines: Vec<String>,
+    pub selected: Option<usize>,
+}
+
+/// A line of text placed on the window, positions and sizes in tiles
+pub struct Label {
+    pub text: String,
+    pub x: f64,
+    pub y: f64,
+    /// The size of a cell of the 3x5 glyphs
+    pub cell: f64,
+    pub color: Color,
+}
+
+impl View {
+    /// The title near the top and the lines below it, one per row, shrunk
+    /// to fit into `width` tiles
+    pub fn layout(&self, width: usize) -> Vec<Label> {
+        // a glyph takes four cells with its spacing
+        let room = width.saturating_sub(2) as f64;
+        let fit = |text: &str, cell: f64| cell.min(room / (4 * text.len().max(1)) as f64);
+        let mut labels = vec![Label {
+            text: self.title.to_string(),
+            x: 1.0,
+            y: 2.0,
+            cell: fit(self.title, 0.25),
+            color: Color::Cyan,
+        }];
+        let lines: Vec<String> = self
+            .lines
+            .iter()
+            .enumerate()
+            .map(|(i, line)| {
+                let marker = if Some(i) == self.selected { "> " } else { "  " };
+                format!("{}{}", marker, line)
+            })
+            .collect();
+        let cell = lines.iter().fold(0.125, |cell, line| fit(line, cell));
+        labels.extend(lines.into_iter().enumerate().map(|(i, text)| Label {
+            text,
+            x: 1.0,
+            y: 5.0 + i as f64,
+            cell,
+            color: if Some(i) == self.selected {
+                Color::Orange
+            } else {
+                Color::Grey
+            },
+        }));
+        labels
+    }
+}
+
+fn switch(on: bool) -> &'static str {
+    if on { "ON" } else { "OFF" }
+}
+
+// the items of a screen, `None` for a line of text
+type Line = (Option<Item>, String);
+
+pub struct Menu {
+    // the open screens with the index of their selected item
+    stack: Vec<(Screen, usize)>,
+    modes: Vec<Mode>,
+    mode: usize,
+    // the chosen level of every mode
+    levels: HashMap<Mode, usize>,
+    initial_stack: usize,
+    max_initial_stack: usize,
+    puzzle_count: usize,
+    // whether a game is going on below the title screen
+    can_continue: bool,
+    // the scores shown, read when their screen opens
+    high_scores: Vec<(&'static str, Vec<usize>)>,
+}
+
+impl Menu {
+    /// A closed menu offering the given modes, the initial stack chosen
+    /// first is the one given on the command line
+    pub fn new(
+        modes: Vec<Mode>,
+        initial_stack: usize,
+        max_initial_stack: usize,
+        puzzle_count: usize,
+    ) -> Menu {
+        let levels = modes
+            .iter()
+            .map(|&mode| (mode, mode.first_level()))
+            .collect();
+        Menu {
+            stack: Vec::new(),
+            modes,
+            mode: 0,
+            levels,
+            initial_stack: initial_stack.min(max_initial_stack),
+            max_initial_stack,
+            puzzle_count,
+            can_continue: false,
+            high_scores: Vec::new(),
+        }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.stack.is_empty()
+    }
+
+    /// Shows the title screen, with an item to continue the current game
+    pub fn open(&mut self, can_continue: bool) {
+        self.can_continue = can_continue;
+        self.stack = vec![(Screen::Title, 0)];
+    }
+
+    pub fn close(&mut self) {
+        self.stack.clear();
+    }
+
+    fn mode(&self) -> Mode {
+        self.modes[self.mode]
+    }
+
+    fn level(&self) -> usize {
+        self.levels[&self.mode()]
+    }
+
+    fn lines(&self, screen: Screen, options: &Options) -> Vec<Line> {
+        let item = |item: Item, text: String| (Some(item), text);
+        match screen {
+            Screen::Title => {
+                let mut lines = Vec::new();
+                if self.can_continue {
+                    lines.push(item(Item::Continue, "CONTINUE".to_string()));
+                }
+                lines.push(item(Item::Play, "PLAY".to_string()));
+                lines.push(item(Item::Mode, format!("MODE < {} >", self.mode().name())));
+                let level = self.level();
+                match self.mode() {
+                    Mode::Marathon | Mode::Master => {
+                        lines.push(item(Item::Level, format!("LEVEL < {} >", level)));
+                        lines.push(item(
+                            Item::Stack,
+                            format!("STACK < {} >", self.initial_stack),
+                        ));
+                    }
+                    Mode::Puzzles => lines.push(item(Item::Level, format!("PUZZLE < {} >", level))),
+                    Mode::Sandbox => {}
+                }
+                lines.push(item(Item::Settings, "SETTINGS".to_string()));
+                lines.push(item(Item::HighScores, "HIGH SCORES".to_string()));
+                lines.push(item(Item::Quit, "QUIT".to_string()));
+                lines
+            }
+            Screen::Settings => vec![
+                item(
+                    Item::Particles,
+                    format!("PARTICLES < {} >", switch(options.particles)),
+                ),
+                item(
+                    Item::ScreenShake,
+                    format!("SHAKE < {} >", switch(options.screen_shake)),
+                ),
+                item(
+                    Item::Patterns,
+                    format!("PATTERNS < {} >", switch(options.patterns)),
+                ),
+                item(
+                    Item::LineClearDelay,
+                    format!("LINE CLEAR < {} >", options.line_clear_delay),
+                ),
+                item(Item::Back, "BACK".to_string()),
+            ],
+            Screen::HighScores => {
+                let mut lines = Vec::new();
+                for (name, scores) in self.high_scores.iter() {
+                    lines.push((None, name.to_string()));
+                    if scores.is_empty() {
+                        lines.push((None, "  -".to_string()));
+                    }
+                    for (rank, score) in scores.iter().enumerate() {
+                        lines.push((None, format!("  {}. {}", rank + 1, score)));
+                    }
+                }
+                lines.push(item(Item::Back, "BACK".to_string()));
+                lines
+            }
+        }
+    }
+
+    /// The top screen
+    pub fn view(&self, options: &Options) -> Option<View> {
+        let &(screen, selected) = self.stack.last()?;
+        let lines = self.lines(screen, options);
+        let selected = lines
+            .iter()
+            .enumerate()
+            .filter(|(_, (item, _))| item.is_some())
+            .nth(selected)
+            .map(|(i, _)| i);
+        Some(View {
+            title: match screen {
+                Screen::Title => "RUSTY TETRIS",
+                Screen::Settings => "SETTINGS",
+                Screen::HighScores => "HIGH SCORES",
+            },
+            lines: lines.into_iter().map(|(_, text)| text).collect(),
+            selected,
+        })
+    }
+
+    pub fn key_press(&mut self, key: Key, options: &Options) -> Option<Action> {
+        let &(screen, selected) = self.stack.last()?;
+        let items: Vec<Item> = self
+            .lines(screen, options)
+            .into_iter()
+            .filter_map(|(item, _)| item)
+            .collect();
+        let item = items[selected.min(items.len() - 1)];
+        match key {
+            Key::Up | Key::W => self.select((selected + items.len() - 1) % items.len()),
+            Key::Down | Key::S => self.select((selected + 1) % items.len()),
+            Key::Left | Key::A => return self.change(item, false, options),
+            Key::Right | Key::D => return self.change(item, true, options),
+            Key::Return | Key::Space => return self.choose(item, options),
+            Key::Escape | Key::Backspace => return self.back(),
+            _ => {}
+        }
+        None
+    }
+
+    fn select(&mut self, index: usize) {
+        if let Some(top) = self.stack.last_mut() {
+            top.1 = index;
+        }
+    }
+
+    fn back(&mut self) -> Option<Action> {
+        if self.stack.len() > 1 {
+            self.stack.pop();
+            None
+        } else if self.can_continue {
+            self.close();
+            Some(Action::Continue)
+        } else {
+            None
+        }
+    }
+
+    fn choose(&mut self, item: Item, options: &Options) -> Option<Action> {
+        match item {
+            Item::Continue => {
+                self.close();
+                Some(Action::Continue)
+            }
+            Item::Play => {
+                self.close();
+                Some(Action::Start(Choice {
+                    mode: self.mode(),
+                    level: self.level(),
+                    initial_stack: self.initial_stack,
+                }))
+            }
+            Item::Settings => {
+                self.stack.push((Screen::Settings, 0));
+                None
+            }
+            Item::HighScores => {
+                self.high_scores = vec![
+                    ("LINES", highscores::high_scores(highscores::MARATHON)),
+                    ("MASTER LEVEL", highscores::high_scores(highscores::MASTER)),
+                ];
+                self.stack.push((Screen::HighScores, 0));
+                None
+            }
+            Item::Quit => Some(Action::Quit),
+            Item::Back => self.back(),
+            _ => self.change(item, true, options),
+        }
+    }
+
+    // steps the value of an item up or down
+    fn change(&mut self, item: Item, up: bool, options: &Options) -> Option<Action> {
+        let step = |value: usize, min: usize, max: usize, by: usize| {
+            if up {
+                (value + by).min(max)
+            } else {
+                value.saturating_sub(by).max(min)
+            }
+        };
+        match item {
+            Item::Mode => {
+                let count = self.modes.len();
+                self.mode = if up {
+                    (self.mode + 1) % count
+                } else {
+                    (self.mode + count - 1) % count
+                };
+            }
+            Item::Level => {
+                let mode = self.mode();
+                let level = self.level();
+                let level = match mode {
+                    Mode::Marathon => step(level, 1, MAX_LEVEL, 1),
+                    Mode::Master => step(level, 0, MAX_MASTER_LEVEL, MASTER_LEVEL_STEP),
+                    Mode::Puzzles => step(level, 1, self.puzzle_count, 1),
+                    Mode::Sandbox => level,
+                };
+                self.levels.insert(mode, level);
+            }
+            Item::Stack => {
+                self.initial_stack = step(self.initial_stack, 0, self.max_initial_stack, 1)
+            }
+            Item::Particles => return Some(Action::Change(Setting::Particles(!options.particles))),
+            Item::ScreenShake => {
+                return Some(Action::Change(Setting::ScreenShake(!options.screen_shake)));
+            }
+            Item::Patterns => return Some(Action::Change(Setting::Patterns(!options.patterns))),
+            Item::LineClearDelay => {
+                let millis = step(
+                    options.line_clear_delay as usize,
+                    0,
+                    MAX_LINE_CLEAR_DELAY as usize,
+                    LINE_CLEAR_DELAY_STEP,
+                );
+                return Some(Action::Change(Setting::LineClearDelay(millis as u32)));
+            }
+            _ => {}
+        }
+        None
+    }
+}
@@ -0,0 +1,415 @@
+//! The pieces the game is played with: the seven standard tetrominoes, the
+//! built-in pentominoes, the tetrominoes at double size or shapes loaded
+//! from a file. A set is chosen once at start, all pieces of it live for
+//! the rest of the game.
+
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::active::ActiveTetromino;
+use crate::board::BUFFER_HEIGHT;
+use crate::tetromino::{Color, Rotation, SHAPES, Tetromino};
+
+static PENTOMINO: &str = include_str!("../bin/assets/pieces/pentomino.txt");
+
+static SELECTED: OnceLock<PieceSet> = OnceLock::new();
+
+pub struct PieceSet {
+    name: String,
+    shapes: &'static [Tetromino],
+}
+
+/// The pieces of the selected set, the standard tetrominoes if none was chosen
+pub fn shapes() -> &'static [Tetromino] {
+    SELECTED.get().map_or(&SHAPES, |set| set.shapes)
+}
+
+/// The name of the selected set as given to `PieceSet::by_name`
+pub fn name() -> &'static str {
+    SELECTED.get().map_or("standard", |set| &set.name)
+}
+
+/// Plays with the given set from now on. Only the first call has an effect
+/// since pieces handed out before must stay valid.
+pub fn select(set: PieceSet) -> Result<(), String> {
+    SELECTED
+        .set(set)
+        .map_err(|_| "the piece set is already chosen".to_string())
+}
+
+// the spawn offset which centers the blocks horizontally with the lowest
+// ones in the same row as those of the standard tetrominoes
+fn centered_spawn(blocks: &[(usize, usize)]) -> (i32, i32) {
+    let left = blocks.iter().map(|&(x, _)| x).min().unwrap_or(0) as i32;
+    let right = blocks.iter().map(|&(x, _)| x).max().unwrap_or(0) as i32;
+    let bottom = blocks.iter().map(|&(_, y)| y).max().unwrap_or(0) as i32;
+    let width = right - left + 1;
+    (2 - left - (width + 1) / 2, 2 - bottom)
+}
+
+// turns the blocks a quarter clockwise around the pivot given in half cells
+fn rotate(blocks: &[(usize, usize)], pivot: (i32, i32)) -> Result<Vec<(usize, usize)>, String> {
+    blocks
+        .iter()
+        .map(|&(x, y)| {
+            let (dx, dy) = (2 * x as i32 - pivot.0, 2 * y as i32 - pivot.1);
+            let (x, y) = (pivot.0 - dy, pivot.1 + dx);
+            if x < 0 || y < 0 || x % 2 != 0 || y % 2 != 0 {
+                Err("rotating around the pivot moves blocks off the grid".to_string())
+            } else {
+                Ok(((x / 2) as usize, (y / 2) as usize))
+            }
+        })
+        .collect()
+}
+
+fn leak(blocks: Vec<(usize, usize)>) -> &'static [(usize, usize)] {
+    Box::leak(blocks.into_boxed_slice())
+}
+
+fn coordinates(value: &str, key: &str) -> Result<(f64, f64), String> {
+    let invalid = || format!("'{}' needs two numbers, not '{}'", key, value);
+    let numbers: Vec<f64> = value
+        .split_whitespace()
+        .map(|n| n.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    match numbers[..] {
+        [x, y] => Ok((x, y)),
+        _ => Err(invalid()),
+    }
+}
+
+/// A piece as read from the file before it is checked
+struct Draft {
+    name: char,
+    color: Option<Color>,
+    pivot: Option<(i32, i32)>,
+    spawn: (i32, i32),
+    // the rows of every shape
+    shapes: Vec<Vec<String>>,
+}
+
+impl Draft {
+    fn new(name: char) -> Draft {
+        Draft {
+            name,
+            color: None,
+            pivot: None,
+            spawn: (0, 0),
+            shapes: Vec::new(),
+        }
+    }
+
+    fn finish(self) -> Result<Tetromino, String> {
+        let color = self.color.ok_or("a piece needs a color")?;
+        let shapes: Vec<Vec<(usize, usize)>> = self
+            .shapes
+            .iter()
+            .map(|rows| {
+                rows.iter()
+                    .enumerate()
+                    .flat_map(|(y, row)| {
+                        row.chars()
+                            .enumerate()
+                            .filter(|&(_, c)| c != '.')
+                            .map(move |(x, _)| (x, y))
+                    })
+                    .collect()
+            })
+            .collect();
+        if shapes.iter().any(|blocks| blocks.is_empty()) {
+            return Err("a shape needs at least one block".to_string());
+        }
+        let points: Vec<Vec<(usize, usize)>> = match shapes.len() {
+            1 => {
+                // the center of the smallest square grid holding the shape
+                // unless given otherwise
+                let rows = &self.shapes[0];
+                let size = rows
+                    .iter()
+                    .map(|row| row.len())
+                    .max()
+                    .unwrap_or(0)
+                    .max(rows.len());
+                let center = size as i32 - 1;
+                let pivot = self.pivot.unwrap_or((center, center));
+                let mut points = vec![shapes[0].clone()];
+                for _ in 0..3 {
+                    points.push(rotate(points.last().unwrap(), pivot)?);
+                }
+                points
+            }
+            _ if self.pivot.is_some() => {
+                return Err("a pivot only applies to a piece with a single shape".to_string());
+            }
+            2 => vec![
+                shapes[0].clone(),
+                shapes[1].clone(),
+                shapes[0].clone(),
+                shapes[1].clone(),
+            ],
+            4 => shapes,
+            n => return Err(format!("a piece needs 1, 2 or 4 shapes, not {}", n)),
+        };
+        if points.iter().any(|blocks| blocks.len() != points[0].len()) {
+            return Err("all shapes of a piece need the same number of blocks".to_string());
+        }
+        let (x, y) = centered_spawn(&points[0]);
+        let spawn = (x + self.spawn.0, y + self.spawn.1);
+        let mut points = points.into_iter().map(leak);
+        let points = [(); 4].map(|_| points.next().unwrap());
+        Ok(Tetromino::new(self.name, color, points, spawn))
+    }
+}
+
+impl PieceSet {
+    /// A built-in set given by its name or a set loaded from a file
+    pub fn by_name(name: &str) -> Result<PieceSet, String> {
+        match name {
+            "standard" => Ok(PieceSet::standard()),
+            "pentomino" => Ok(PieceSet::parse(PENTOMINO, name).expect("built-in pieces are valid")),
+            "big" => Ok(PieceSet::big()),
+            _ => PieceSet::load(Path::new(name)),
+        }
+    }
+
+    pub fn standard() -> PieceSet {
+        PieceSet {
+            name: "standard".to_string(),
+            shapes: &SHAPES,
+        }
+    }
+
+    /// The standard tetrominoes with every block doubled in both directions
+    pub fn big() -> PieceSet {
+        let shapes: Vec<Tetromino> = SHAPES
+            .iter()
+            .map(|shape| {
+                let points =
+                    [Rotation::R0, Rotation::R1, Rotation::R2, Rotation::R3].map(|rotation| {
+                        shape
+                            .points(rotation)
+                            .iter()
+                            .flat_map(|&(x, y)| {
+                                [(0, 0), (1, 0), (0, 1), (1, 1)]
+                                    .map(|(dx, dy)| (2 * x + dx, 2 * y + dy))
+                            })
+                            .collect::<Vec<_>>()
+                    });
+                let spawn = centered_spawn(&points[0]);
+                Tetromino::new(shape.get_name(), shape.get_color(), points.map(leak), spawn)
+            })
+            .collect();
+        PieceSet {
+            name: "big".to_string(),
+            shapes: Box::leak(shapes.into_boxed_slice()),
+        }
+    }
+
+    /// Parses a piece set like
+    ///
+    /// ```text
+    /// # comments start with a hash
+    /// piece: T
+    /// color: purple
+    /// pivot: 1 1
+    /// spawn: 0 -1
+    /// shape:
+    /// XXX
+    /// .X.
+    /// ```
+    ///
+    /// Every piece is named by a letter and needs one of the colors cyan,
+    /// blue, orange, yellow, lime, purple, red or grey. A single shape is
+    /// rotated clockwise around the pivot, given in cells from the top left
+    /// one and by default the center of the smallest square grid holding the
+    /// shape. Halves like `1.5` are allowed. Alternatively all rotation states
+    /// are given as two or four shapes. Pieces spawn centered right above the
+    /// board, `spawn` moves them by the given columns and rows.
+    pub fn parse(text: &str, name: &str) -> Result<PieceSet, String> {
+        let mut drafts: Vec<Draft> = Vec::new();
+        let mut in_shape = false;
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                let draft = drafts
+                    .last_mut()
+                    .filter(|_| in_shape)
+                    .ok_or_else(|| format!("expected 'key: value' but found '{}'", line))?;
+                if let Some(c) = line.chars().find(|&c| !matches!(c, '.' | 'X' | 'x')) {
+                    return Err(format!(
+                        "piece {}: unknown cell '{}', use '.' or 'X'",
+                        draft.name, c
+                    ));
+                }
+                draft.shapes.last_mut().unwrap().push(line.to_string());
+                continue;
+            };
+            let value = value.trim();
+            in_shape = false;
+            if key.trim() == "piece" {
+                let mut letters = value.chars();
+                let name = match (letters.next(), letters.next()) {
+                    (Some(c), None) if c.is_ascii_alphabetic() => c.to_ascii_uppercase(),
+                    _ => return Err(format!("a piece is named by a letter, not '{}'", value)),
+                };
+                if drafts.iter().any(|draft| draft.name == name) {
+                    return Err(format!("there is more than one piece {}", name));
+                }
+                drafts.push(Draft::new(name));
+                continue;
+            }
+            let draft = drafts
+                .last_mut()
+                .ok_or_else(|| format!("'{}' before the first piece", key.trim()))?;
+            let context = |e: String| format!("piece {}: {}", draft.name, e);
+            match key.trim() {
+                "color" => {
+                    draft.color = Some(
+                        Color::from_name(value)
+                            .ok_or_else(|| context(format!("unknown color '{}'", value)))?,
+                    )
+                }
+                "pivot" => {
+                    let (x, y) = coordinates(value, "pivot").map_err(context)?;
+                    let (x, y) = (x * 2.0, y * 2.0);
+                    if x.fract() != 0.0 || y.fract() != 0.0 || x < 0.0 || y < 0.0 {
+                        return Err(context(format!("invalid pivot '{}'", value)));
+                    }
+                    draft.pivot = Some((x as i32, y as i32));
+                }
+                "spawn" => {
+                    let (x, y) = coordinates(value, "spawn").map_err(context)?;
+                    if x.fract() != 0.0 || y.fract() != 0.0 {
+                        return Err(context(format!("invalid spawn offset '{}'", value)));
+                    }
+                    draft.spawn = (x as i32, y as i32);
+                }
+                "shape" => {
+                    draft.shapes.push(Vec::new());
+                    in_shape = true;
+                }
+                other => return Err(format!("unknown key '{}'", other)),
+            }
+        }
+        if drafts.is_empty() {
+            return Err("a piece set needs at least one piece".to_string());
+        }
+        let shapes = drafts
+            .into_iter()
+            .map(|draft| {
+                let name = draft.name;
+                draft.finish().map_err(|e| format!("piece {}: {}", name, e))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(PieceSet {
+            name: name.to_string(),
+            shapes: Box::leak(shapes.into_boxed_slice()),
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<PieceSet, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        PieceSet::parse(&text, &path.display().to_string())
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Number of columns taken by the widest rotation state of any piece
+    pub fn width(&self) -> usize {
+        self.shapes
+            .iter()
+            .flat_map(|shape| {
+                [Rotation::R0, Rotation::R1, Rotation::R2, Rotation::R3].map(|rotation| {
+                    let xs = shape.points(rotation).iter().map(|&(x, _)| x);
+                    xs.clone().max().unwrap_or(0) - xs.min().unwrap_or(0) + 1
+                })
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Whether every piece spawns on a board of the given width, height and
+    /// rows of the vanishing zone, and can be turned in all rotation states
+    /// without leaving its columns
+    pub fn check(&self, width: usize, height: usize, buffer: usize) -> Result<(), String> {
+        if self.width() > width {
+            return Err(format!(
+                "the pieces need a board at least {} columns wide",
+                self.width()
+            ));
+        }
+        for shape in self.shapes {
+            let (x, y, _) = ActiveTetromino::spawn(shape, width, buffer).position();
+            let mut columns = [Rotation::R0, Rotation::R1, Rotation::R2, Rotation::R3]
+                .iter()
+                .flat_map(|&rotation| shape.points(rotation))
+                .map(|&(dx, _)| x + dx as i32);
+            if columns.any(|x| x < 0 || x >= width as i32) {
+                return Err(format!(
+                    "piece {}: the spawn offset moves it off a board {} columns wide",
+                    shape.get_name(),
+                    width
+                ));
+            }
+            // high pieces are moved down by the spawn, low ones may fall through
+            let mut rows = shape
+                .points(Rotation::R0)
+                .iter()
+                .map(|&(_, dy)| y + dy as i32);
+            if rows.any(|y| y >= (BUFFER_HEIGHT + height) as i32) {
+                return Err(format!(
+                    "piece {}: the spawn offset moves it below a board {} rows high",
+                    shape.get_name(),
+                    height
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn domino(spawn: &str) -> PieceSet {
+        let text = format!("piece: D\ncolor: red\nspawn: {}\nshape:\nXX\n", spawn);
+        PieceSet::parse(&text, "domino").unwrap()
+    }
+
+    #[test]
+    fn accepts_the_builtin_sets_on_a_standard_board() {
+        for buffer in [0, 1, 2, 20] {
+            assert_eq!(PieceSet::standard().check(10, 20, buffer), Ok(()));
+            assert_eq!(PieceSet::big().check(10, 20, buffer), Ok(()));
+            let pentomino = PieceSet::by_name("pentomino").unwrap();
+            assert_eq!(pentomino.check(10, 20, buffer), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_a_spawn_offset_off_the_sides() {
+        assert_eq!(domino("4 0").check(10, 20, 20), Ok(()));
+        assert_eq!(
+            domino("5 0").check(10, 20, 20).err().as_deref(),
+            Some("piece D: the spawn offset moves it off a board 10 columns wide")
+        );
+        assert_eq!(
+            domino("-5 0").check(10, 20, 20).err().as_deref(),
+            Some("piece D: the spawn offset moves it off a board 10 columns wide")
+        );
+    }
+
+    #[test]
+    fn rejects_a_spawn_offset_below_the_board() {
+        assert_eq!(domino("0 18").check(10, 20, 0), Ok(()));
+        assert_eq!(
+            domino("0 19").check(10, 20, 0).err().as_deref(),
+            Some("piece D: the spawn offset moves it below a board 20 rows high")
+        );
+    }
+}
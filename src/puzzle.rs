@@ -0,0 +1,225 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+use crate::board::{Board, DEFAULT_HEIGHT, DEFAULT_WIDTH};
+use crate::board_text;
+use crate::piece_set;
+use crate::storage;
+use crate::tetromino::{SHAPES, Tetromino};
+
+const SOLVED_FILE: &str = "solved_puzzles.txt";
+
+static BUILTIN_PUZZLES: [&str; 5] = [
+    include_str!("../bin/assets/puzzles/tetris.txt"),
+    include_str!("../bin/assets/puzzles/perfect-clear.txt"),
+    include_str!("../bin/assets/puzzles/t-spin-double.txt"),
+    include_str!("../bin/assets/puzzles/chimney.txt"),
+    include_str!("../bin/assets/puzzles/survivor.txt"),
+];
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Goal {
+    Lines(usize),
+    TSpinDouble,
+    PerfectClear,
+    Survive(usize),
+}
+
+impl std::fmt::Display for Goal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Goal::Lines(n) => write!(f, "lines {}", n),
+            Goal::TSpinDouble => write!(f, "tspin-double"),
+            Goal::PerfectClear => write!(f, "perfect-clear"),
+            Goal::Survive(n) => write!(f, "survive {}", n),
+        }
+    }
+}
+
+/// Progress of the player within a puzzle
+#[derive(Copy, Clone, Default)]
+pub struct Progress {
+    pub lines: usize,
+    pub pieces: usize,
+    pub t_spin_doubles: usize,
+    pub perfect_clear: bool,
+}
+
+impl Goal {
+    fn parse(text: &str) -> Result<Goal, String> {
+        let mut words = text.split_whitespace();
+        let count = |word: Option<&str>| {
+            word.and_then(|w| w.parse::<usize>().ok())
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("goal '{}' needs a positive count", text))
+        };
+        match words.next() {
+            Some("lines") => Ok(Goal::Lines(count(words.next())?)),
+            Some("tspin-double") => Ok(Goal::TSpinDouble),
+            Some("perfect-clear") => Ok(Goal::PerfectClear),
+            Some("survive") => Ok(Goal::Survive(count(words.next())?)),
+            _ => Err(format!("unknown goal '{}'", text)),
+        }
+    }
+
+    pub fn is_reached(&self, progress: &Progress) -> bool {
+        match *self {
+            Goal::Lines(n) => progress.lines >= n,
+            Goal::TSpinDouble => progress.t_spin_doubles > 0,
+            Goal::PerfectClear => progress.perfect_clear,
+            Goal::Survive(n) => progress.pieces >= n,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Puzzle {
+    pub name: String,
+    pub goal: Option<Goal>,
+    pub pieces: Vec<&'static Tetromino>,
+    pub hold: Option<&'static Tetromino>,
+    pub board: Board,
+}
+
+impl Puzzle {
+    /// Parses a puzzle description like
+    ///
+    /// ```text
+    /// # comments start with a hash and may precede the board
+    /// name: T-spin double
+    /// goal: tspin-double
+    /// pieces: T
+    /// board:
+    /// GGGG......
+    /// GGG...GGGG
+    /// GGGG.GGGGG
+    /// ```
+    ///
+    /// Goals are `lines <n>`, `tspin-double`, `perfect-clear` and `survive <n>`.
+    /// Without a goal the puzzle is a sandbox where random pieces follow the
+    /// given ones. An optional `hold: <piece>` sets the held piece.
+    /// The board is given in the format of `board_text`.
+    pub fn parse(text: &str) -> Result<Puzzle, String> {
+        Puzzle::parse_with(text, piece_set::shapes())
+    }
+
+    // parses a puzzle whose pieces are looked up in the given set
+    fn parse_with(text: &str, shapes: &'static [Tetromino]) -> Result<Puzzle, String> {
+        let piece = |c: char| {
+            shapes
+                .iter()
+                .find(|shape| shape.get_name() == c.to_ascii_uppercase())
+                .ok_or(format!("unknown piece '{}'", c))
+        };
+        let mut name = None;
+        let mut goal = None;
+        let mut pieces = Vec::new();
+        let mut hold = None;
+        let mut rows = Vec::new();
+        let mut lines = text.lines();
+        for line in lines.by_ref() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'key: value' but found '{}'", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "name" => name = Some(value.to_string()),
+                "goal" => goal = Some(Goal::parse(value)?),
+                "pieces" => {
+                    pieces = value
+                        .chars()
+                        .filter(|c| !c.is_whitespace())
+                        .map(piece)
+                        .collect::<Result<_, _>>()?
+                }
+                "hold" => {
+                    let mut names = value.chars();
+                    hold = match (names.next(), names.next()) {
+                        (None, _) => None,
+                        (Some(c), None) => Some(piece(c)?),
+                        _ => return Err(format!("only one piece can be held, not '{}'", value)),
+                    };
+                }
+                "board" => break,
+                other => return Err(format!("unknown key '{}'", other)),
+            }
+        }
+        for line in lines {
+            let line = line.trim();
+            if !line.is_empty() {
+                rows.push(line);
+            }
+        }
+        if pieces.is_empty() && goal.is_some() {
+            return Err("a puzzle needs at least one piece".to_string());
+        }
+        let board = board_text::parse(&rows.join("\n"), DEFAULT_WIDTH, DEFAULT_HEIGHT)?;
+        Ok(Puzzle {
+            name: name.ok_or("a puzzle needs a name")?,
+            goal,
+            pieces,
+            hold,
+            board,
+        })
+    }
+
+    /// The puzzle in the format understood by `parse`
+    pub fn to_text(&self) -> String {
+        let mut text = format!("name: {}\n", self.name);
+        if let Some(goal) = self.goal {
+            text.push_str(&format!("goal: {}\n", goal));
+        }
+        let pieces: String = self.pieces.iter().map(|p| p.get_name()).collect();
+        text.push_str(&format!("pieces: {}\n", pieces));
+        if let Some(hold) = self.hold {
+            text.push_str(&format!("hold: {}\n", hold.get_name()));
+        }
+        text.push_str("board:\n");
+        let board = board_text::format(&self.board, None);
+        // leave out the empty rows at the top
+        let rows = board
+            .lines()
+            .skip_while(|row| row.chars().all(|c| c == '.'));
+        for row in rows {
+            text.push_str(row);
+            text.push('\n');
+        }
+        text
+    }
+
+    pub fn load(path: &Path) -> Result<Puzzle, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Puzzle::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The built-in puzzles, made for the standard tetrominoes whatever the
+    /// selected piece set
+    pub fn builtins() -> Vec<Puzzle> {
+        BUILTIN_PUZZLES
+            .iter()
+            .map(|text| Puzzle::parse_with(text, &SHAPES).expect("built-in puzzles are valid"))
+            .collect()
+    }
+}
+
+/// Names of all puzzles the player has solved so far
+pub fn solved_puzzles() -> BTreeSet<String> {
+    storage::data_file(SOLVED_FILE)
+        .and_then(fs::read_to_string)
+        .map(|text| text.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+pub fn mark_solved(name: &str) -> std::io::Result<()> {
+    let mut solved = solved_puzzles();
+    if solved.insert(name.to_string()) {
+        let text: String = solved.iter().map(|n| format!("{}\n", n)).collect();
+        fs::write(storage::data_file(SOLVED_FILE)?, text)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,290 @@
+//! A new endless or master game is recorded while it's played and the
+//! recording is kept as `replay.txt` in the data directory when the next
+//! game starts or the game closes. A replay
+//! holds the seed the board and the tetrominoes were drawn from, the options
+//! the game depends on and every key moving, rotating, holding or dropping
+//! the tetromino pressed and released together with the tick it happened
+//! before, so the game plays out the same way again:
+//!
+//! ```text
+//! rusty-tetris replay 1
+//! seed: 4021799931
+//! piece set: standard
+//! size: 10x20
+//! ...
+//! inputs:
+//! 75 press 1073741904
+//! 81 release 1073741904
+//! ```
+//!
+//! Keys are given by their key codes. Replays are rendered headless to an
+//! animated GIF or a sequence of PNG frames.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::str::FromStr;
+
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame};
+use piston_window::Key;
+
+use crate::screenshot;
+use crate::storage;
+use crate::tetris::Tetris;
+use crate::theme::Skin;
+
+const REPLAY_FILE: &str = "replay.txt";
+const VERSION: &str = "rusty-tetris replay 1";
+
+/// Seconds the game keeps running after the last input of a replay
+const TAIL_SECONDS: u64 = 2;
+
+/// A key pressed or released before the simulation of a tick
+#[derive(Copy, Clone)]
+pub struct Input {
+    pub tick: u64,
+    pub key: Key,
+    pub pressed: bool,
+}
+
+pub struct Replay {
+    pub seed: u64,
+    /// The piece set as given to `PieceSet::by_name`
+    pub piece_set: String,
+    pub width: usize,
+    pub height: usize,
+    pub initial_stack_size: usize,
+    pub buffer: usize,
+    pub partial_row: bool,
+    pub practice: bool,
+    pub master: bool,
+    pub start_level: usize,
+    pub tick_rate: u32,
+    pub line_clear_delay: f64,
+    pub rotate_180: Key,
+    pub inputs: Vec<Input>,
+}
+
+// a field parsed as a number or a flag
+fn value<T: FromStr>(fields: &HashMap<&str, &str>, key: &str) -> Result<T, String> {
+    let value = fields
+        .get(key)
+        .ok_or_else(|| format!("missing '{}'", key))?;
+    value
+        .parse()
+        .map_err(|_| format!("invalid {} '{}'", key, value))
+}
+
+impl Replay {
+    /// Sets up the game to be recorded or played back, the settings which
+    /// don't change how the game plays out are left to the caller
+    pub fn start(&self, skin: Option<Skin>) -> Tetris {
+        let mut game = Tetris::new(skin, self.initial_stack_size, self.width, self.height);
+        game.set_rotate_180_key(self.rotate_180);
+        game.set_line_clear_delay(self.line_clear_delay);
+        game.set_buffer(self.buffer);
+        game.set_partial_row(self.partial_row);
+        game.set_practice(self.practice);
+        game.set_master(self.master);
+        game.set_start_level(self.start_level);
+        game.set_high_scores(false);
+        game.set_tick_rate(self.tick_rate);
+        // the board and the bag are drawn from the seed once all else is set
+        game.set_seed(self.seed);
+        game
+    }
+
+    /// Plays the inputs back on a game set up by `start` and hands it to
+    /// `frame` `fps` times per second of game time, starting right away.
+    /// Pauses are skipped.
+    pub fn play(
+        &self,
+        game: &mut Tetris,
+        fps: u32,
+        mut frame: impl FnMut(&Tetris) -> Result<(), String>,
+    ) -> Result<(), String> {
+        let end =
+            self.inputs.last().map_or(0, |input| input.tick) + TAIL_SECONDS * self.tick_rate as u64;
+        let mut inputs = self.inputs.iter().peekable();
+        let mut frames = 0;
+        while game.tick_count() < end {
+            while let Some(input) = inputs.next_if(|input| input.tick <= game.tick_count()) {
+                if input.pressed {
+                    game.key_press(&input.key);
+                } else {
+                    game.key_release(&input.key);
+                }
+            }
+            // a frame as soon as its time has come, the first before any tick
+            while frames * self.tick_rate as u64 <= game.tick_count() * fps as u64 {
+                frame(game)?;
+                frames += 1;
+            }
+            if !game.advance() {
+                match inputs.peek() {
+                    // the recording ended paused
+                    None => break,
+                    Some(input) => {
+                        return Err(format!(
+                            "the game stands still at tick {} but the next input is at tick {}",
+                            game.tick_count(),
+                            input.tick
+                        ));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Renders the replay to an animated GIF if `output` ends with `.gif`
+    /// and to numbered PNG files in the directory `output` otherwise.
+    /// Returns the number of frames.
+    pub fn export(
+        &self,
+        game: &mut Tetris,
+        output: &Path,
+        fps: u32,
+        tile: u32,
+    ) -> Result<usize, String> {
+        let error = |e: &dyn std::fmt::Display| format!("{}: {}", output.display(), e);
+        let mut count = 0;
+        let is_gif = output
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("gif"));
+        if is_gif {
+            let file = File::create(output).map_err(|e| error(&e))?;
+            let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), 10);
+            encoder
+                .set_repeat(Repeat::Infinite)
+                .map_err(|e| error(&e))?;
+            let delay = Delay::from_numer_denom_ms(1000, fps);
+            self.play(game, fps, |game| {
+                count += 1;
+                let image = screenshot::render(&game.frame(), tile);
+                encoder
+                    .encode_frame(Frame::from_parts(image, 0, 0, delay))
+                    .map_err(|e| error(&e))
+            })?;
+        } else {
+            fs::create_dir_all(output).map_err(|e| error(&e))?;
+            self.play(game, fps, |game| {
+                let path = output.join(format!("frame-{:05}.png", count));
+                count += 1;
+                screenshot::render(&game.frame(), tile)
+                    .save(&path)
+                    .map_err(|e| format!("{}: {}", path.display(), e))
+            })?;
+        }
+        Ok(count)
+    }
+
+    /// The replay in the format understood by `parse`
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", VERSION);
+        text.push_str(&format!("seed: {}\n", self.seed));
+        text.push_str(&format!("piece set: {}\n", self.piece_set));
+        text.push_str(&format!("size: {}x{}\n", self.width, self.height));
+        text.push_str(&format!("initial stack: {}\n", self.initial_stack_size));
+        text.push_str(&format!("buffer: {}\n", self.buffer));
+        text.push_str(&format!("partial row: {}\n", self.partial_row));
+        text.push_str(&format!("practice: {}\n", self.practice));
+        text.push_str(&format!("master: {}\n", self.master));
+        text.push_str(&format!("start level: {}\n", self.start_level));
+        text.push_str(&format!("tick rate: {}\n", self.tick_rate));
+        text.push_str(&format!("line clear delay: {}\n", self.line_clear_delay));
+        text.push_str(&format!("rotate 180: {}\n", u32::from(self.rotate_180)));
+        text.push_str("inputs:\n");
+        for input in self.inputs.iter() {
+            let action = if input.pressed { "press" } else { "release" };
+            text.push_str(&format!(
+                "{} {} {}\n",
+                input.tick,
+                action,
+                u32::from(input.key)
+            ));
+        }
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<Replay, String> {
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(VERSION) => {}
+            Some(version) => return Err(format!("unsupported replay format '{}'", version)),
+            None => return Err("the replay is empty".to_string()),
+        }
+        let mut fields = HashMap::new();
+        for line in lines.by_ref() {
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'key: value' but found '{}'", line))?;
+            if key == "inputs" {
+                break;
+            }
+            fields.insert(key.trim(), value.trim());
+        }
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or_else(|| format!("missing '{}'", key))
+        };
+        let size = field("size")?;
+        let (width, height) = size
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .ok_or_else(|| format!("invalid size '{}'", size))?;
+
+        let inputs = lines
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let invalid = || format!("invalid input '{}'", line);
+                match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    [tick, action, key] => Ok(Input {
+                        tick: tick.parse().map_err(|_| invalid())?,
+                        key: Key::from(key.parse::<u32>().map_err(|_| invalid())?),
+                        pressed: match action {
+                            "press" => true,
+                            "release" => false,
+                            _ => return Err(invalid()),
+                        },
+                    }),
+                    _ => Err(invalid()),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if inputs.windows(2).any(|pair| pair[1].tick < pair[0].tick) {
+            return Err("the inputs are out of order".to_string());
+        }
+
+        Ok(Replay {
+            seed: value(&fields, "seed")?,
+            piece_set: field("piece set")?.to_string(),
+            width,
+            height,
+            initial_stack_size: value(&fields, "initial stack")?,
+            buffer: value(&fields, "buffer")?,
+            partial_row: value(&fields, "partial row")?,
+            practice: value(&fields, "practice")?,
+            master: value(&fields, "master")?,
+            start_level: value(&fields, "start level")?,
+            tick_rate: value(&fields, "tick rate")?,
+            line_clear_delay: value(&fields, "line clear delay")?,
+            rotate_180: Key::from(value::<u32>(&fields, "rotate 180")?),
+            inputs,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Replay, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Replay::parse(&text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Keeps the replay as the one of the last game
+    pub fn store(&self) -> io::Result<()> {
+        fs::write(storage::data_file(REPLAY_FILE)?, self.to_text())
+    }
+}
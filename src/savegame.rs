@@ -0,0 +1,192 @@
+//! The endless game is saved when the window closes and resumed at the next
+//! start. The save file is plain text starting with a version line; files of
+//! another version are rejected and a new game starts instead.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+
+use crate::active::ActiveTetromino;
+use crate::board::Board;
+use crate::board_text;
+use crate::piece_set;
+use crate::storage;
+use crate::tetromino::{Rotation, Tetromino};
+
+const SAVE_FILE: &str = "savegame.txt";
+const VERSION: &str = "rusty-tetris save 4";
+
+pub struct SavedGame {
+    pub board: Board,
+    pub active: ActiveTetromino,
+    pub next_shape: Option<&'static Tetromino>,
+    pub hold: Option<&'static Tetromino>,
+    pub hold_used: bool,
+    pub bag: Vec<&'static Tetromino>,
+    pub line_count: usize,
+    pub tetromino_count: usize,
+    pub gravity_factor: f64,
+    pub gravity_accumulator: f64,
+    pub time: f64,
+}
+
+fn piece(name: char) -> Result<&'static Tetromino, String> {
+    Tetromino::from_name(name).ok_or(format!("unknown piece '{}'", name))
+}
+
+fn optional_piece(value: &str) -> Result<Option<&'static Tetromino>, String> {
+    value.chars().next().map(piece).transpose()
+}
+
+fn optional_name(piece: Option<&'static Tetromino>) -> String {
+    piece.map(Tetromino::get_name).into_iter().collect()
+}
+
+impl SavedGame {
+    /// The saved game in the format understood by `parse`
+    pub fn to_text(&self) -> String {
+        let (x, y, rotation) = self.active.position();
+        let mut text = format!("{}\n", VERSION);
+        text.push_str(&format!("piece set: {}\n", piece_set::name()));
+        text.push_str(&format!(
+            "size: {}x{}\n",
+            self.board.width(),
+            self.board.height()
+        ));
+        text.push_str(&format!("lines: {}\n", self.line_count));
+        text.push_str(&format!("pieces: {}\n", self.tetromino_count));
+        text.push_str(&format!("gravity: {}\n", self.gravity_factor));
+        text.push_str(&format!("fall: {}\n", self.gravity_accumulator));
+        text.push_str(&format!("time: {}\n", self.time));
+        text.push_str(&format!(
+            "active: {} {} {} {}\n",
+            self.active.shape().get_name(),
+            x,
+            y,
+            rotation.quarter_turns()
+        ));
+        text.push_str(&format!("next: {}\n", optional_name(self.next_shape)));
+        text.push_str(&format!("hold: {}\n", optional_name(self.hold)));
+        text.push_str(&format!("hold used: {}\n", self.hold_used));
+        text.push_str(&format!(
+            "bag: {}\n",
+            self.bag.iter().map(|p| p.get_name()).collect::<String>()
+        ));
+        text.push_str("board:\n");
+        text.push_str(&board_text::format(&self.board, None));
+        text
+    }
+
+    pub fn parse(text: &str) -> Result<SavedGame, String> {
+        let mut lines = text.lines();
+        match lines.next() {
+            Some(VERSION) => {}
+            Some(version) => return Err(format!("unsupported save format '{}'", version)),
+            None => return Err("the save file is empty".to_string()),
+        }
+        let mut fields = HashMap::new();
+        for line in lines.by_ref() {
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'key: value' but found '{}'", line))?;
+            if key == "board" {
+                break;
+            }
+            fields.insert(key.trim(), value.trim());
+        }
+        let field = |key: &str| {
+            fields
+                .get(key)
+                .copied()
+                .ok_or_else(|| format!("missing '{}'", key))
+        };
+        let number = |key: &str| {
+            let value = field(key)?;
+            value
+                .parse::<f64>()
+                .map_err(|_| format!("'{}' is not a number: '{}'", key, value))
+        };
+        let count = |key: &str| {
+            let value = field(key)?;
+            value
+                .parse::<usize>()
+                .map_err(|_| format!("'{}' is not a count: '{}'", key, value))
+        };
+
+        // the names of the pieces depend on the set
+        let set = field("piece set")?;
+        if set != piece_set::name() {
+            return Err(format!(
+                "the game was played with the piece set '{}', not '{}'",
+                set,
+                piece_set::name()
+            ));
+        }
+
+        let size = field("size")?;
+        let (width, height) = size
+            .split_once('x')
+            .and_then(|(w, h)| Some((w.parse().ok()?, h.parse().ok()?)))
+            .ok_or_else(|| format!("invalid size '{}'", size))?;
+
+        let active = field("active")?;
+        let parts: Vec<&str> = active.split_whitespace().collect();
+        let active = match parts[..] {
+            [name, x, y, turns] => {
+                let shape = piece(name.chars().next().unwrap_or(' '))?;
+                let invalid = || format!("invalid active piece '{}'", active);
+                ActiveTetromino::at(
+                    shape,
+                    x.parse().map_err(|_| invalid())?,
+                    y.parse().map_err(|_| invalid())?,
+                    turns
+                        .parse()
+                        .ok()
+                        .and_then(Rotation::from_quarter_turns)
+                        .ok_or_else(invalid)?,
+                )
+            }
+            _ => return Err(format!("invalid active piece '{}'", active)),
+        };
+
+        Ok(SavedGame {
+            board: board_text::parse(&lines.collect::<Vec<_>>().join("\n"), width, height)?,
+            active,
+            next_shape: optional_piece(field("next")?)?,
+            hold: optional_piece(field("hold")?)?,
+            hold_used: field("hold used")?
+                .parse()
+                .map_err(|_| "'hold used' is neither true nor false".to_string())?,
+            bag: field("bag")?.chars().map(piece).collect::<Result<_, _>>()?,
+            line_count: count("lines")?,
+            tetromino_count: count("pieces")?,
+            gravity_factor: number("gravity")?,
+            gravity_accumulator: number("fall")?,
+            time: number("time")?,
+        })
+    }
+
+    /// The saved game, `None` if there is none
+    pub fn load() -> Result<Option<SavedGame>, String> {
+        let path = storage::data_file(SAVE_FILE).map_err(|e| e.to_string())?;
+        match fs::read_to_string(&path) {
+            Ok(text) => SavedGame::parse(&text)
+                .map(Some)
+                .map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn store(&self) -> io::Result<()> {
+        fs::write(storage::data_file(SAVE_FILE)?, self.to_text())
+    }
+}
+
+/// Forgets the saved game so the next start begins a new one
+pub fn remove() -> io::Result<()> {
+    match fs::remove_file(storage::data_dir().join(SAVE_FILE)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
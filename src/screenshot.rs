@@ -0,0 +1,216 @@
+//! Draws the game into an image without a window, so a state can be saved
+//! as a picture for bug reports or compared in tests. Blocks are drawn as
+//! flat squares in the colors of the theme, the ghost as an outline where
+//! the falling tetromino would land.
+
+use image::{Rgba, RgbaImage};
+
+use crate::hud;
+use crate::tetris::{Frame, LAYOUT_ROWS, SIDE_BAR_WIDTH};
+use crate::tetromino::{Color, Rotation, Tetromino};
+use crate::theme;
+
+/// The picture of a frame, `tile` pixels per block
+pub fn render(frame: &Frame, tile: u32) -> RgbaImage {
+    let width = frame.rows.first().map_or(0, |row| row.len()) as u32;
+    let full_rows = frame.rows.len() - usize::from(frame.partial_row);
+    let partial_height = if frame.partial_row { tile / 2 } else { 0 };
+    let layout_rows = full_rows.max(LAYOUT_ROWS) as u32;
+    let mut canvas = Canvas {
+        image: RgbaImage::new(
+            (width + SIDE_BAR_WIDTH as u32) * tile,
+            layout_rows * tile + partial_height,
+        ),
+    };
+    let (image_width, image_height) = canvas.image.dimensions();
+    canvas.fill(0, 0, image_width, image_height, theme::background());
+    if let Some(view) = frame.menu.as_ref() {
+        for label in view.layout(width as usize + SIDE_BAR_WIDTH) {
+            let cell = ((label.cell * tile as f64) as u32).max(1);
+            let (left, top) = (label.x * tile as f64, label.y * tile as f64);
+            for (x, y) in hud::text_cells(&label.text) {
+                canvas.fill(
+                    left as i64 + (x as u32 * cell) as i64,
+                    top as i64 + (y as u32 * cell) as i64,
+                    cell,
+                    cell,
+                    label.color.as_rgba(),
+                );
+            }
+        }
+        return canvas.image;
+    }
+    // the partial row sticks out above the image by half a tile
+    let top =
+        |y: usize| (y as i64 - frame.partial_row as i64) * tile as i64 + partial_height as i64;
+    for (y, row) in frame.rows.iter().enumerate() {
+        for (x, cell) in row.iter().enumerate() {
+            let Some(color) = cell else { continue };
+            let rgba = if frame.clearing.contains(&y) {
+                [1.0; 4]
+            } else {
+                color.as_rgba()
+            };
+            canvas.block(x as i64 * tile as i64, top(y), tile, rgba);
+        }
+    }
+    if let Some((color, points)) = frame.ghost.as_ref() {
+        for &(x, y) in points.iter().filter(|&&(x, y)| frame.rows[y][x].is_none()) {
+            canvas.outline(x as i64 * tile as i64, top(y), tile, color.as_rgba());
+        }
+    }
+    // the side bar with the next and the held tetromino and the level or
+    // the number of the puzzle near its bottom
+    let side_bar = (width * tile) as i64;
+    canvas.fill(
+        side_bar,
+        0,
+        SIDE_BAR_WIDTH as u32 * tile,
+        image_height,
+        frame.side_bar_color,
+    );
+    if let Some(next) = frame.next {
+        canvas.shape(next, side_bar + tile as i64, 0, tile);
+    }
+    if let Some(hold) = frame.hold {
+        canvas.shape(hold, side_bar + tile as i64, 12 * tile as i64, tile);
+    }
+    if let Some(number) = frame.level.or(frame.puzzle) {
+        let cell = (tile / 5).max(1);
+        let bottom = ((layout_rows - 2) * tile) as i64;
+        for (x, y) in hud::number_cells(number) {
+            canvas.fill(
+                side_bar + (tile + x as u32 * cell) as i64,
+                bottom + (y as u32 * cell) as i64,
+                cell,
+                cell,
+                Color::Cyan.as_rgba(),
+            );
+        }
+    }
+    canvas.image
+}
+
+struct Canvas {
+    image: RgbaImage,
+}
+
+impl Canvas {
+    /// Blends a rectangle in, the parts outside of the image are left out
+    fn fill(&mut self, x: i64, y: i64, width: u32, height: u32, color: [f32; 4]) {
+        let (image_width, image_height) = self.image.dimensions();
+        let [r, g, b, a] = color;
+        for py in y.max(0)..(y + height as i64).min(image_height as i64) {
+            for px in x.max(0)..(x + width as i64).min(image_width as i64) {
+                let pixel = self.image.get_pixel_mut(px as u32, py as u32);
+                let Rgba([pr, pg, pb, pa]) = *pixel;
+                let blend =
+                    |new: f32, old: u8| (new * 255.0 * a + old as f32 * (1.0 - a)).round() as u8;
+                *pixel = Rgba([
+                    blend(r, pr),
+                    blend(g, pg),
+                    blend(b, pb),
+                    (a * 255.0 + pa as f32 * (1.0 - a)).round() as u8,
+                ]);
+            }
+        }
+    }
+
+    /// A block with a darker border
+    fn block(&mut self, x: i64, y: i64, size: u32, color: [f32; 4]) {
+        let [r, g, b, a] = color;
+        self.fill(x, y, size, size, [r * 0.6, g * 0.6, b * 0.6, a]);
+        let border = (size / 8).max(1);
+        self.fill(
+            x + border as i64,
+            y + border as i64,
+            size.saturating_sub(2 * border),
+            size.saturating_sub(2 * border),
+            color,
+        );
+    }
+
+    fn outline(&mut self, x: i64, y: i64, size: u32, color: [f32; 4]) {
+        let line = (size / 16).max(1);
+        let far = (size - line) as i64;
+        self.fill(x, y, size, line, color);
+        self.fill(x, y + far, size, line, color);
+        self.fill(x, y, line, size, color);
+        self.fill(x + far, y, line, size, color);
+    }
+
+    /// A tetromino as in the side bar, larger pieces are shrunk to the four
+    /// tiles of a tetromino
+    fn shape(&mut self, shape: &'static Tetromino, x: i64, y: i64, tile: u32) {
+        let points = shape.points(Rotation::R0);
+        let extent = points.iter().map(|&(x, y)| x.max(y) + 1).max().unwrap_or(0) as u32;
+        let tile = if extent > 4 { tile * 4 / extent } else { tile };
+        for &(px, py) in points.iter() {
+            self.block(
+                x + (px as u32 * tile) as i64,
+                y + (py as u32 * tile) as i64,
+                tile,
+                shape.get_color().as_rgba(),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TILE: u32 = 10;
+    const SIDE_BAR: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+    fn rgba(color: [f32; 4]) -> Rgba<u8> {
+        Rgba(color.map(|c| (c * 255.0).round() as u8))
+    }
+
+    // a 10x20 board with a red block at the bottom of the third column and
+    // a ghost at the bottom of the last one
+    fn frame(partial_row: bool) -> Frame {
+        let mut rows = vec![vec![None; 10]; 20 + usize::from(partial_row)];
+        let bottom = rows.len() - 1;
+        rows[bottom][2] = Some(Color::Red);
+        Frame {
+            rows,
+            partial_row,
+            clearing: Vec::new(),
+            ghost: Some((Color::Cyan, vec![(9, bottom)])),
+            next: None,
+            hold: None,
+            lines: 0,
+            level: None,
+            puzzle: None,
+            status: None,
+            side_bar_color: SIDE_BAR,
+            menu: None,
+        }
+    }
+
+    #[test]
+    fn draws_the_board_and_the_side_bar() {
+        let image = render(&frame(false), TILE);
+        assert_eq!(image.dimensions(), (15 * TILE, 20 * TILE));
+        // an empty cell, the red block with its darker border, the outline of
+        // the ghost and the side bar
+        assert_eq!(*image.get_pixel(5, 5), rgba(theme::background()));
+        assert_eq!(*image.get_pixel(25, 195), rgba(Color::Red.as_rgba()));
+        let [r, g, b, a] = Color::Red.as_rgba();
+        assert_eq!(
+            *image.get_pixel(20, 195),
+            rgba([r * 0.6, g * 0.6, b * 0.6, a])
+        );
+        assert_eq!(*image.get_pixel(90, 195), rgba(Color::Cyan.as_rgba()));
+        assert_eq!(*image.get_pixel(95, 195), rgba(theme::background()));
+        assert_eq!(*image.get_pixel(120, 100), rgba(SIDE_BAR));
+    }
+
+    #[test]
+    fn shows_the_partial_row_half_as_high() {
+        let image = render(&frame(true), TILE);
+        assert_eq!(image.dimensions(), (15 * TILE, 20 * TILE + TILE / 2));
+        assert_eq!(*image.get_pixel(25, 200), rgba(Color::Red.as_rgba()));
+    }
+}
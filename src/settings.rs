@@ -0,0 +1,234 @@
+//! Preferences read from `settings.txt` in the data directory, one
+//! `key: value` per line:
+//!
+//! ```text
+//! # comments start with a hash
+//! rotate 180: W
+//! line clear delay: 250
+//! particles: on
+//! screen shake: off
+//! theme: default
+//! palette: deuteranopia
+//! patterns: on
+//! window size: 600x800
+//! ```
+//!
+//! The window size is stored whenever the game ends.
+
+use std::fs;
+use std::io;
+
+use piston_window::Key;
+
+use crate::storage;
+
+const SETTINGS_FILE: &str = "settings.txt";
+
+/// Smallest window size which is remembered
+const MIN_WINDOW_SIZE: u32 = 100;
+
+/// Longest line clear delay in milliseconds
+pub const MAX_LINE_CLEAR_DELAY: u32 = 1000;
+
+static NAMED_KEYS: [(&str, Key); 13] = [
+    ("Space", Key::Space),
+    ("Tab", Key::Tab),
+    ("Return", Key::Return),
+    ("Up", Key::Up),
+    ("Down", Key::Down),
+    ("Left", Key::Left),
+    ("Right", Key::Right),
+    ("LShift", Key::LShift),
+    ("RShift", Key::RShift),
+    ("LCtrl", Key::LCtrl),
+    ("RCtrl", Key::RCtrl),
+    ("LAlt", Key::LAlt),
+    ("RAlt", Key::RAlt),
+];
+
+// the keys with a fixed meaning in the game, the half turn can't take them
+static BOUND_KEYS: [(Key, &str); 18] = [
+    (Key::E, "rotates clockwise"),
+    (Key::Q, "rotates counterclockwise"),
+    (Key::Up, "rotates counterclockwise"),
+    (Key::A, "moves left"),
+    (Key::Left, "moves left"),
+    (Key::D, "moves right"),
+    (Key::Right, "moves right"),
+    (Key::S, "soft drops"),
+    (Key::Down, "soft drops"),
+    (Key::Space, "drops the piece"),
+    (Key::C, "holds the piece"),
+    (Key::LShift, "holds the piece"),
+    (Key::RShift, "holds the piece"),
+    (Key::P, "pauses the game"),
+    (Key::N, "skips to the next puzzle"),
+    (Key::Z, "undoes a placement"),
+    (Key::Y, "redoes a placement"),
+    (Key::Tab, "opens the editor"),
+];
+
+/// A letter, a digit or one of the named keys like `Space` or `LShift`
+fn key_from_name(name: &str) -> Option<Key> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next()) {
+        // letters and digits are encoded by their lower case character
+        (Some(c), None) if c.is_ascii_alphanumeric() => {
+            Some(Key::from(c.to_ascii_lowercase() as u32))
+        }
+        _ => NAMED_KEYS
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|&(_, key)| key),
+    }
+}
+
+fn switch(value: &str) -> Result<bool, String> {
+    match value {
+        "on" => Ok(true),
+        "off" => Ok(false),
+        _ => Err(format!("expected 'on' or 'off' but found '{}'", value)),
+    }
+}
+
+pub struct Settings {
+    pub rotate_180: Key,
+    /// Seconds cleared lines are animated before the next tetromino appears
+    pub line_clear_delay: f64,
+    pub particles: bool,
+    pub screen_shake: bool,
+    /// The built-in default theme, an installed one or a theme directory
+    pub theme: String,
+    /// A built-in palette replacing the colors of the theme
+    pub palette: Option<String>,
+    /// Whether blocks carry a pattern telling their colors apart
+    pub patterns: bool,
+    /// The size of the window when the game was last closed
+    pub window_size: Option<[u32; 2]>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            rotate_180: Key::W,
+            line_clear_delay: 0.0,
+            particles: true,
+            screen_shake: false,
+            theme: "default".to_string(),
+            palette: None,
+            patterns: false,
+            window_size: None,
+        }
+    }
+}
+
+impl Settings {
+    pub fn parse(text: &str) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'key: value' but found '{}'", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "rotate 180" => {
+                    let key = key_from_name(value).ok_or(format!("unknown key '{}'", value))?;
+                    if let Some((_, action)) = BOUND_KEYS.iter().find(|(bound, _)| *bound == key) {
+                        return Err(format!("the key '{}' already {}", value, action));
+                    }
+                    settings.rotate_180 = key;
+                }
+                "line clear delay" => {
+                    let millis = value
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&ms| ms <= MAX_LINE_CLEAR_DELAY)
+                        .ok_or(format!(
+                            "the line clear delay takes 0 to {} milliseconds, not '{}'",
+                            MAX_LINE_CLEAR_DELAY, value
+                        ))?;
+                    settings.line_clear_delay = millis as f64 / 1000.0;
+                }
+                "particles" => settings.particles = switch(value)?,
+                "screen shake" => settings.screen_shake = switch(value)?,
+                "theme" => settings.theme = value.to_string(),
+                "palette" => settings.palette = Some(value.to_string()),
+                "patterns" => settings.patterns = switch(value)?,
+                "window size" => {
+                    let size = value
+                        .split_once('x')
+                        .and_then(|(w, h)| Some([w.trim().parse().ok()?, h.trim().parse().ok()?]))
+                        .filter(|size: &[u32; 2]| size.iter().all(|&n| n >= MIN_WINDOW_SIZE))
+                        .ok_or(format!(
+                            "expected a window size like 600x800 but found '{}'",
+                            value
+                        ))?;
+                    settings.window_size = Some(size);
+                }
+                other => return Err(format!("unknown setting '{}'", other)),
+            }
+        }
+        Ok(settings)
+    }
+
+    /// Remembers the window size in the settings file
+    pub fn store_window_size(size: [u32; 2]) -> Result<(), String> {
+        Settings::store("window size", &format!("{}x{}", size[0], size[1]))
+    }
+
+    /// Sets one line of the settings file, keeping all other lines
+    pub fn store(key: &str, value: &str) -> Result<(), String> {
+        let path = storage::data_file(SETTINGS_FILE).map_err(|e| e.to_string())?;
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("{}: {}", path.display(), e)),
+        };
+        let mut lines: Vec<&str> = text
+            .lines()
+            .filter(|line| line.split_once(':').is_none_or(|(k, _)| k.trim() != key))
+            .collect();
+        let line = format!("{}: {}", key, value);
+        lines.push(&line);
+        fs::write(&path, lines.join("\n") + "\n").map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// The stored settings, the defaults if there are none
+    pub fn load() -> Result<Settings, String> {
+        let path = storage::data_dir().join(SETTINGS_FILE);
+        match fs::read_to_string(&path) {
+            Ok(text) => Settings::parse(&text).map_err(|e| format!("{}: {}", path.display(), e)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Settings::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn binds_the_half_turn_to_a_free_key() {
+        let settings = Settings::parse("rotate 180: x").ok().unwrap();
+        assert_eq!(settings.rotate_180, Key::X);
+    }
+
+    #[test]
+    fn rejects_a_half_turn_key_which_is_already_bound() {
+        let error = Settings::parse("rotate 180: P").err();
+        assert_eq!(
+            error.as_deref(),
+            Some("the key 'P' already pauses the game")
+        );
+        let error = Settings::parse("rotate 180: Space").err();
+        assert_eq!(
+            error.as_deref(),
+            Some("the key 'Space' already drops the piece")
+        );
+    }
+}
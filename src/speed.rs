@@ -0,0 +1,132 @@
+//! How fast the game runs: the gravity, the delay before the next tetromino
+//! appears (ARE), the extra delay after clearing lines and the time a landed
+//! tetromino may still be moved before it locks. Arcade timings are given in
+//! frames of a 60 Hz display.
+
+const FPS: f64 = 60.0;
+
+/// Simulation steps per second unless configured otherwise
+pub const DEFAULT_TICK_RATE: u32 = 60;
+
+/// Soft dropping runs the gravity this many times as fast
+pub const SOFT_DROP_FACTOR: f64 = 15.0;
+
+/// Gravity at which a tetromino falls to the ground within a single frame
+pub const MAX_GRAVITY: f64 = 20.0;
+
+/// Soft dropping falls at least this fast in master mode
+pub const SOFT_DROP_GRAVITY: f64 = 1.0;
+
+/// The gravity factor of the endless game moves a tetromino by one row each
+/// time this much of it has accumulated
+const GRAVITY_FACTOR_PER_ROW: f64 = 0.35;
+
+/// The last level of the master mode, reaching it completes the game
+pub const MASTER_LEVELS: usize = 999;
+
+#[derive(Copy, Clone)]
+pub struct Timing {
+    /// Rows per frame (G), up to `MAX_GRAVITY`
+    pub gravity: f64,
+    /// Seconds before the next tetromino appears
+    pub are: f64,
+    /// Seconds added to the ARE when lines were cleared
+    pub line_clear: f64,
+    /// Seconds a tetromino lies on the ground before it locks. Without a
+    /// lock delay it locks as soon as gravity can't move it any further.
+    pub lock_delay: Option<f64>,
+}
+
+impl Timing {
+    /// Rows a tetromino falls per second
+    pub fn rows_per_second(&self) -> f64 {
+        self.gravity.min(MAX_GRAVITY) * FPS
+    }
+}
+
+/// The endless game, only cleared lines hold up the next tetromino
+pub fn normal(gravity_factor: f64, line_clear: f64) -> Timing {
+    Timing {
+        gravity: gravity_factor / GRAVITY_FACTOR_PER_ROW / FPS,
+        are: 0.0,
+        line_clear,
+        lock_delay: None,
+    }
+}
+
+// levels where the gravity changes and the new gravity in 1/256 G
+static MASTER_GRAVITY: [(usize, u32); 30] = [
+    (0, 4),
+    (30, 6),
+    (35, 8),
+    (40, 10),
+    (50, 12),
+    (60, 16),
+    (70, 32),
+    (80, 48),
+    (90, 64),
+    (100, 80),
+    (120, 96),
+    (140, 112),
+    (160, 128),
+    (170, 144),
+    (200, 4),
+    (220, 32),
+    (230, 64),
+    (233, 96),
+    (236, 128),
+    (239, 160),
+    (243, 192),
+    (247, 224),
+    (251, 256),
+    (300, 512),
+    (330, 768),
+    (360, 1024),
+    (400, 1280),
+    (420, 1024),
+    (450, 768),
+    (500, 5120),
+];
+
+// levels where the delays change and the new ARE, line clear and lock delay
+// in frames
+static MASTER_DELAYS: [(usize, u32, u32, u32); 6] = [
+    (0, 25, 40, 30),
+    (500, 25, 25, 30),
+    (600, 25, 16, 30),
+    (700, 16, 12, 30),
+    (800, 12, 6, 30),
+    (900, 12, 6, 17),
+];
+
+/// The master mode: the gravity rises with the level up to 20G from level
+/// 500 on, afterwards the delays shrink
+pub fn master(level: usize) -> Timing {
+    let (_, gravity) = MASTER_GRAVITY
+        .iter()
+        .rev()
+        .find(|&&(from, _)| level >= from)
+        .unwrap_or(&MASTER_GRAVITY[0]);
+    let &(_, are, line_clear, lock_delay) = MASTER_DELAYS
+        .iter()
+        .rev()
+        .find(|&&(from, ..)| level >= from)
+        .unwrap_or(&MASTER_DELAYS[0]);
+    Timing {
+        gravity: *gravity as f64 / 256.0,
+        are: are as f64 / FPS,
+        line_clear: line_clear as f64 / FPS,
+        lock_delay: Some(lock_delay as f64 / FPS),
+    }
+}
+
+/// The master level after a tetromino spawned (`lines` is `None`) or after
+/// clearing lines. A new piece doesn't advance the last level of a section,
+/// only cleared lines move on to the next one.
+pub fn next_master_level(level: usize, lines: Option<usize>) -> usize {
+    match lines {
+        Some(lines) => (level + lines).min(MASTER_LEVELS),
+        None if level % 100 == 99 || level >= MASTER_LEVELS - 1 => level,
+        None => level + 1,
+    }
+}
@@ -0,0 +1,24 @@
+use std::env;
+use std::path::PathBuf;
+
+/// Directory for everything the game remembers between runs.
+/// Falls back to the working directory if no home directory is known.
+pub fn data_dir() -> PathBuf {
+    let base = if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+        PathBuf::from(dir)
+    } else if let Some(dir) = env::var_os("APPDATA") {
+        PathBuf::from(dir)
+    } else if let Some(home) = env::var_os("HOME") {
+        PathBuf::from(home).join(".local").join("share")
+    } else {
+        PathBuf::from(".")
+    };
+    base.join("rusty-tetris")
+}
+
+/// Path of a file within the data directory, creating the directory if needed
+pub fn data_file(name: &str) -> std::io::Result<PathBuf> {
+    let dir = data_dir();
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir.join(name))
+}
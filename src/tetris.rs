@@ -1,29 +1,79 @@
 #![allow(clippy::identity_op)]
-use piston_window::wgpu_graphics::Texture;
+use image::RgbaImage;
 use piston_window::{
-    Key, UpdateArgs,
-    graphics::{Context, Graphics, Image, Transformed, rectangle},
+    Key, MouseButton, UpdateArgs,
+    graphics::{Context, Graphics, Image, Rectangle, Transformed, math::Matrix2d, rectangle},
 };
-use rand::Rng;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::BTreeSet;
 use std::default::Default;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use crate::active::ActiveTetromino;
+use crate::board::{BUFFER_HEIGHT, Board};
+use crate::board_text;
+use crate::editor::{self, Editor};
+use crate::effects::Effects;
+use crate::evaluator::{self, Placement, Verdict};
+use crate::finesse::{self, FinesseStats};
+use crate::fumen::{self, Position};
+use crate::highscores;
+use crate::hud;
+use crate::menu::{self, Choice, Mode, Options};
+use crate::piece_set;
+use crate::puzzle::{self, Goal, Progress, Puzzle};
+use crate::replay::{Input, Replay};
+use crate::savegame::{self, SavedGame};
+use crate::screenshot;
+use crate::speed::{self, Timing};
+use crate::storage;
 use crate::tetris::State::*;
-use crate::tetromino::{Color, Rotation, Tetromino, TetrominoBag};
+use crate::tetromino::{Color, FixedSequence, Randomizer, Rotation, Tetromino, TetrominoBag};
+use crate::theme::{self, Skin};
 
-pub const WINDOW_WIDTH: u32 = 600;
-pub const WINDOW_HEIGHT: u32 = 800;
-pub const BOARD_WIDTH: usize = 10;
-pub const BOARD_HEIGHT: usize = 20;
-static TILE_SIZE: f64 = 40.0;
+pub static TILE_SIZE: f64 = 40.0;
+/// Width of the side bar in tiles
+pub const SIDE_BAR_WIDTH: usize = 5;
+/// Rows the side bar needs, lower boards leave room below them and higher
+/// boards are zoomed out to the height of this many rows
+pub const LAYOUT_ROWS: usize = 20;
+/// Height of the half row of the vanishing zone shown above the board
+pub const PARTIAL_ROW_HEIGHT: u32 = 20;
 
 pub const UPDATE_TIME: f64 = 0.15;
 
+/// Longest stretch of time simulated after a single frame, a longer hitch
+/// slows the game down instead of running a burst of ticks
+const MAX_FRAME_TIME: f64 = 0.25;
+
+// boards higher than the layout are zoomed out to keep the window height
+fn layout_zoom(height: usize) -> f64 {
+    LAYOUT_ROWS as f64 / height.max(LAYOUT_ROWS) as f64
+}
+
+/// The window size for a board with the given number of columns and visible
+/// rows, optionally with the partial row above it
+pub fn window_size(width: usize, height: usize, scale: f64, partial_row: bool) -> [u32; 2] {
+    let zoom = scale * layout_zoom(height);
+    let rows = height.max(LAYOUT_ROWS);
+    let partial_row = if partial_row { PARTIAL_ROW_HEIGHT } else { 0 };
+    [
+        ((width + SIDE_BAR_WIDTH) as f64 * TILE_SIZE * zoom).round() as u32,
+        ((rows as f64 * TILE_SIZE + partial_row as f64) * zoom).round() as u32,
+    ]
+}
+
 #[derive(PartialEq, Copy, Clone)]
 enum State {
     Playing,
     Dropping,
+    // the delay between locking a tetromino and the next one appearing
+    Waiting,
     Defeated,
+    Solved,
+    Editing,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -82,6 +132,10 @@ impl KeyState {
         self.press_count > 0
     }
 
+    fn is_held(&self) -> bool {
+        !matches!(self.state_type, KeyStateType::Released)
+    }
+
     fn reset(&mut self) {
         self.state_type = KeyStateType::Released;
         self.press_count = 0;
@@ -91,8 +145,104 @@ impl KeyState {
 pub struct ControlState {
     rotate_right: KeyState,
     rotate_left: KeyState,
+    rotate_180: KeyState,
     move_left: KeyState,
     move_right: KeyState,
+    // whether a hold key is down
+    hold: bool,
+}
+
+impl ControlState {
+    fn new() -> ControlState {
+        ControlState {
+            rotate_right: KeyState::new(),
+            rotate_left: KeyState::new(),
+            rotate_180: KeyState::new(),
+            move_left: KeyState::new(),
+            move_right: KeyState::new(),
+            hold: false,
+        }
+    }
+}
+
+/// The game right after a tetromino spawned, kept in practice mode to step
+/// back and forth between placements
+#[derive(Clone)]
+struct Snapshot {
+    board: Board,
+    active_shape: &'static Tetromino,
+    next_shape: Option<&'static Tetromino>,
+    hold: Option<&'static Tetromino>,
+    bag: Box<dyn Randomizer>,
+    tetromino_count: usize,
+    line_count: usize,
+    gravity_factor: f64,
+    level: usize,
+    puzzle_progress: Progress,
+    finesse_stats: FinesseStats,
+}
+
+/// Text the game reports while it's played, left to the front end to show
+/// so it doesn't write over the terminal
+pub enum Message {
+    Info(String),
+    Error(String),
+}
+
+impl Message {
+    pub fn text(&self) -> &str {
+        match self {
+            Message::Info(text) | Message::Error(text) => text,
+        }
+    }
+
+    /// Prints to the standard output, errors to the standard error
+    pub fn print(&self) {
+        match self {
+            Message::Info(text) => println!("{}", text),
+            Message::Error(text) => eprintln!("{}", text),
+        }
+    }
+}
+
+/// The game as shown by a frontend without textures like the terminal
+pub struct Frame {
+    /// The visible rows, the partial row included, with the falling tetromino
+    pub rows: Vec<Vec<Option<Color>>>,
+    /// Whether the first row is the partial row, shown half as high
+    pub partial_row: bool,
+    /// Indices of the rows which are being cleared
+    pub clearing: Vec<usize>,
+    /// The color of the falling tetromino and where it would land, as
+    /// (column, row) within `rows`
+    pub ghost: Option<(Color, Vec<(usize, usize)>)>,
+    pub next: Option<&'static Tetromino>,
+    pub hold: Option<&'static Tetromino>,
+    pub lines: usize,
+    /// The level of the master mode
+    pub level: Option<usize>,
+    /// The number of the current puzzle
+    pub puzzle: Option<usize>,
+    pub status: Option<&'static str>,
+    pub side_bar_color: [f32; 4],
+    /// The open menu screen, shown instead of the game
+    pub menu: Option<menu::View>,
+}
+
+/// Full rows shown fading out before the rows above them collapse
+struct LineClear {
+    // the board right after the tetromino locked, still holding the full rows
+    board: Board,
+    rows: Vec<usize>,
+    duration: f64,
+    remaining: f64,
+}
+
+impl LineClear {
+    // how far the animation has progressed, from 0 to 1
+    fn progress(&self) -> f64 {
+        1.0 - self.remaining / self.duration
+    }
 }
 
 pub struct Tetris {
@@ -102,56 +252,989 @@ pub struct Tetris {
     tetromino_count: usize,
     line_count: usize,
     active_tetromino: ActiveTetromino,
-    next_shape: &'static Tetromino,
-    board: [[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT],
+    next_shape: Option<&'static Tetromino>,
+    board: Board,
     state: State,
     control_state: ControlState,
     time: f64,
-    block: Texture,
+    // the textures of the window, a terminal has none
+    skin: Option<Skin>,
     paused: bool,
     scale: f64,
-    bag: TetrominoBag,
+    // the top left corner of the board within the window
+    origin: [f64; 2],
+    bag: Box<dyn Randomizer>,
+    show_hint: bool,
+    hint: Option<Placement>,
+    coach: bool,
+    verdict: Option<Verdict>,
+    flagged_points: Vec<(usize, usize)>,
+    finesse: bool,
+    finesse_inputs: usize,
+    finesse_stats: FinesseStats,
+    last_move_rotation: bool,
+    puzzles: Vec<Puzzle>,
+    puzzle_index: usize,
+    puzzle_progress: Progress,
+    solved_puzzles: BTreeSet<String>,
+    start_board: Option<Board>,
+    start_hold: Option<&'static Tetromino>,
+    hold: Option<&'static Tetromino>,
+    hold_used: bool,
+    editor: Option<Editor>,
+    practice: bool,
+    history: Vec<Snapshot>,
+    history_index: usize,
+    buffer: usize,
+    show_partial_row: bool,
+    rotate_180_key: Key,
+    master: bool,
+    level: usize,
+    // seconds the tetromino has been lying on the ground
+    lock_timer: f64,
+    // seconds until the next tetromino appears
+    spawn_delay: f64,
+    // seconds simulated by one tick
+    tick: f64,
+    // seconds passed but not simulated yet
+    tick_accumulator: f64,
+    // seconds cleared lines are animated outside the master mode
+    line_clear_delay: f64,
+    line_clear: Option<LineClear>,
+    effects: Effects,
+    // whether blocks carry the pattern of their color
+    patterns: bool,
+    // fills the initial stack, seeded for replays
+    rng: StdRng,
+    // ticks simulated since the start
+    tick_count: u64,
+    // the inputs of the game so far if it's recorded
+    recording: Option<Replay>,
+    // the editor isn't recorded, the recording ends when it opens
+    recording_ended: bool,
+    // the level of the endless or master game at its start
+    start_level: usize,
+    // whether the results of games are kept as high scores
+    high_scores: bool,
+    // whether the end of the game has been dealt with
+    ended: bool,
+    // reported but not shown yet
+    messages: Vec<Message>,
 }
 
 impl Tetris {
-    pub fn new(scale: f64, texture: Texture, initial_stack_size: usize) -> Tetris {
-        let stack_size = if initial_stack_size < BOARD_HEIGHT {
+    pub fn new(
+        skin: Option<Skin>,
+        initial_stack_size: usize,
+        width: usize,
+        height: usize,
+    ) -> Tetris {
+        let stack_size = if initial_stack_size < height {
             initial_stack_size
         } else {
-            BOARD_HEIGHT - 1
+            height - 1
         };
         let mut bag = TetrominoBag::new();
+        let mut rng = StdRng::from_os_rng();
         Tetris {
             initial_stack_size: stack_size,
             gravity_accumulator: 0.0,
             gravity_factor: 0.5,
             tetromino_count: 0,
             line_count: 0,
-            active_tetromino: ActiveTetromino::new(bag.next().unwrap()),
-            next_shape: bag.next().unwrap(),
-            board: Tetris::create_board(stack_size),
+            active_tetromino: ActiveTetromino::new(bag.next().unwrap(), width),
+            next_shape: bag.next(),
+            board: Tetris::create_board(stack_size, width, height, &mut rng),
             state: Playing,
-            control_state: ControlState {
-                rotate_right: KeyState::new(),
-                rotate_left: KeyState::new(),
-                move_left: KeyState::new(),
-                move_right: KeyState::new(),
-            },
+            control_state: ControlState::new(),
             time: UPDATE_TIME,
-            block: texture,
+            skin,
             paused: false,
-            scale,
-            bag,
+            scale: layout_zoom(height),
+            origin: [0.0, 0.0],
+            bag: Box::new(bag),
+            show_hint: false,
+            hint: None,
+            coach: false,
+            verdict: None,
+            flagged_points: Vec::new(),
+            finesse: false,
+            finesse_inputs: 0,
+            finesse_stats: FinesseStats::default(),
+            last_move_rotation: false,
+            puzzles: Vec::new(),
+            puzzle_index: 0,
+            puzzle_progress: Progress::default(),
+            solved_puzzles: BTreeSet::new(),
+            start_board: None,
+            start_hold: None,
+            hold: None,
+            hold_used: false,
+            editor: None,
+            practice: false,
+            history: Vec::new(),
+            history_index: 0,
+            buffer: BUFFER_HEIGHT,
+            show_partial_row: false,
+            rotate_180_key: Key::W,
+            master: false,
+            level: 0,
+            lock_timer: 0.0,
+            spawn_delay: 0.0,
+            tick: 1.0 / speed::DEFAULT_TICK_RATE as f64,
+            tick_accumulator: 0.0,
+            line_clear_delay: 0.0,
+            line_clear: None,
+            effects: Effects::new(true, false),
+            patterns: false,
+            rng,
+            tick_count: 0,
+            recording: None,
+            recording_ended: false,
+            start_level: 0,
+            high_scores: true,
+            ended: false,
+            messages: Vec::new(),
+        }
+    }
+
+    /// Keeps a snapshot after every placement to undo and redo placements
+    pub fn set_practice(&mut self, practice: bool) {
+        self.practice = practice;
+        self.history.clear();
+        if practice {
+            self.record_snapshot();
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            board: self.board.clone(),
+            active_shape: self.active_tetromino.shape(),
+            next_shape: self.next_shape,
+            hold: self.hold,
+            bag: self.bag.clone(),
+            tetromino_count: self.tetromino_count,
+            line_count: self.line_count,
+            gravity_factor: self.gravity_factor,
+            level: self.level,
+            puzzle_progress: self.puzzle_progress,
+            finesse_stats: self.finesse_stats,
+        }
+    }
+
+    fn restore(&mut self, snapshot: Snapshot) {
+        self.board = snapshot.board;
+        self.next_shape = snapshot.next_shape;
+        self.hold = snapshot.hold;
+        self.bag = snapshot.bag;
+        self.tetromino_count = snapshot.tetromino_count;
+        self.line_count = snapshot.line_count;
+        self.gravity_factor = snapshot.gravity_factor;
+        self.level = snapshot.level;
+        self.puzzle_progress = snapshot.puzzle_progress;
+        self.finesse_stats = snapshot.finesse_stats;
+        self.state = Playing;
+        self.ended = false;
+        self.gravity_accumulator = 0.0;
+        self.lock_timer = 0.0;
+        self.line_clear = None;
+        self.effects.clear();
+        self.hold_used = false;
+        self.verdict = None;
+        self.flagged_points.clear();
+        self.spawn(snapshot.active_shape);
+    }
+
+    // drops the undone placements, they can't be redone after a new one
+    fn record_snapshot(&mut self) {
+        self.history.truncate(self.history_index + 1);
+        self.history.push(self.snapshot());
+        self.history_index = self.history.len() - 1;
+    }
+
+    /// Takes back the last placement. A lost or solved game returns to the
+    /// start of the placement which ended it.
+    fn undo(&mut self) {
+        if let Playing | Dropping | Waiting = self.state {
+            if self.history_index == 0 {
+                return;
+            }
+            self.history_index -= 1;
+        }
+        if let Some(snapshot) = self.history.get(self.history_index).cloned() {
+            self.restore(snapshot);
+            // a rewound game can't be played back, the recording ends
+            self.recording_ended = true;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(snapshot) = self.history.get(self.history_index + 1).cloned() {
+            self.history_index += 1;
+            self.restore(snapshot);
+            self.recording_ended = true;
+        }
+    }
+
+    /// Opens the sandbox editor on the current board and upcoming tetrominoes
+    pub fn open_editor(&mut self) {
+        let queue = match self.state {
+            Playing | Dropping | Waiting => vec![self.active_tetromino.shape()],
+            Defeated | Solved | Editing => Vec::new(),
+        };
+        let queue = queue.into_iter().chain(self.next_shape).collect();
+        self.edit(self.board.clone(), queue, self.hold);
+    }
+
+    fn edit(
+        &mut self,
+        board: Board,
+        queue: Vec<&'static Tetromino>,
+        hold: Option<&'static Tetromino>,
+    ) {
+        self.editor = Some(Editor::new(board, queue, hold));
+        self.state = Editing;
+        self.recording_ended = true;
+        self.line_clear = None;
+        self.effects.clear();
+    }
+
+    fn close_editor(&mut self) {
+        if let Some(editor) = self.editor.take() {
+            self.puzzles.clear();
+            self.start_board = Some(editor.board);
+            self.start_hold = editor.hold;
+            self.bag = Box::new(FixedSequence::new(editor.queue).followed_by_bag());
+            self.play_again();
+        }
+    }
+
+    fn save_editor(&mut self) -> std::io::Result<()> {
+        if let Some(editor) = self.editor.as_ref() {
+            let sandbox = Puzzle {
+                name: "Sandbox".to_string(),
+                goal: None,
+                pieces: editor.queue.clone(),
+                hold: editor.hold,
+                board: editor.board.clone(),
+            };
+            let path = storage::data_file("sandbox.txt")?;
+            std::fs::write(&path, sandbox.to_text())?;
+            self.report(Message::Info(format!(
+                "Saved the sandbox to {}",
+                path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Plays the master mode: the level rises with every piece and cleared
+    /// line up to 999 and sets the gravity up to 20G and the delays
+    pub fn set_master(&mut self, master: bool) {
+        if self.master != master {
+            self.master = master;
+            self.play_again();
+        }
+    }
+
+    /// Starts over with the initial stack and the tetrominoes determined by
+    /// the seed, a replay plays the same game again
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+        self.bag = Box::new(TetrominoBag::seeded(self.rng.random()));
+        self.play_again();
+    }
+
+    pub fn tick_count(&self) -> u64 {
+        self.tick_count
+    }
+
+    /// The number of columns and of visible rows
+    pub fn board_size(&self) -> (usize, usize) {
+        (self.board.width(), self.board.height())
+    }
+
+    pub fn initial_stack_size(&self) -> usize {
+        self.initial_stack_size
+    }
+
+    /// Starts a new endless or master game with a random seed and records
+    /// it from its first tick, the recording of the previous game is kept
+    pub fn start_recording(&mut self) {
+        self.store_replay();
+        self.tick_count = 0;
+        self.time = UPDATE_TIME;
+        self.tick_accumulator = 0.0;
+        self.control_state = ControlState::new();
+        self.paused = false;
+        self.recording_ended = false;
+        let seed = rand::random();
+        self.set_seed(seed);
+        self.recording = Some(Replay {
+            seed,
+            piece_set: piece_set::name().to_string(),
+            width: self.board.width(),
+            height: self.board.height(),
+            initial_stack_size: self.initial_stack_size,
+            buffer: self.buffer,
+            partial_row: self.show_partial_row,
+            practice: self.practice,
+            master: self.master,
+            start_level: self.start_level,
+            tick_rate: (1.0 / self.tick).round() as u32,
+            line_clear_delay: self.line_clear_delay,
+            rotate_180: self.rotate_180_key,
+            inputs: Vec::new(),
+        });
+    }
+
+    /// Keeps the recorded game as the last replay, unless nothing was played
+    pub fn store_replay(&mut self) {
+        if let Some(replay) = self.recording.as_ref()
+            && !replay.inputs.is_empty()
+            && let Err(e) = replay.store()
+        {
+            self.report(Message::Error(format!("Could not store the replay: {}", e)));
+        }
+    }
+
+    /// Leaves a message to the front end
+    pub fn report(&mut self, message: Message) {
+        self.messages.push(message);
+    }
+
+    /// The messages reported since the last call, the oldest first
+    pub fn take_messages(&mut self) -> Vec<Message> {
+        std::mem::take(&mut self.messages)
+    }
+
+    /// Starts the endless game at the given level and the master game at the
+    /// given master level
+    pub fn set_start_level(&mut self, level: usize) {
+        self.start_level = level;
+        self.play_again();
+    }
+
+    /// Whether finished endless and master games are kept as high scores
+    pub fn set_high_scores(&mut self, high_scores: bool) {
+        self.high_scores = high_scores;
+    }
+
+    /// The settings the menu shows
+    pub fn options(&self) -> Options {
+        Options {
+            particles: self.effects.shows_particles(),
+            screen_shake: self.effects.shows_shake(),
+            patterns: self.patterns,
+            line_clear_delay: (self.line_clear_delay * 1000.0).round() as u32,
+        }
+    }
+
+    /// Starts the game chosen in the menu
+    pub fn start(&mut self, choice: Choice) {
+        self.editor = None;
+        self.puzzles.clear();
+        self.start_board = None;
+        self.start_hold = None;
+        self.master = choice.mode == Mode::Master;
+        match choice.mode {
+            Mode::Marathon | Mode::Master => {
+                self.start_level = choice.level;
+                self.initial_stack_size = choice.initial_stack;
+                self.start_recording();
+            }
+            Mode::Puzzles => {
+                self.store_replay();
+                self.recording = None;
+                self.load_puzzles(Puzzle::builtins(), choice.level - 1);
+            }
+            Mode::Sandbox => {
+                self.store_replay();
+                self.recording = None;
+                let board = Board::new(self.board.width(), self.board.height());
+                self.edit(board, Vec::new(), None);
+            }
+        }
+    }
+
+    // deals with a lost or finished game once
+    fn end_game(&mut self) {
+        self.ended = true;
+        if self.finesse {
+            self.report_finesse_stats();
+        }
+        if self.puzzle().is_some() {
+            return;
+        }
+        if self.state == Defeated {
+            self.show_result();
+        }
+        if !self.high_scores || self.practice || self.start_board.is_some() {
+            return;
+        }
+        let (mode, score) = if self.master {
+            (highscores::MASTER, self.level)
+        } else {
+            (highscores::MARATHON, self.line_count)
+        };
+        match highscores::add(mode, score) {
+            Ok(Some(rank)) => self.report(Message::Info(format!(
+                "New high score: {} is number {}",
+                score, rank
+            ))),
+            Ok(None) => {}
+            Err(e) => self.report(Message::Error(format!(
+                "Could not store the high score: {}",
+                e
+            ))),
+        }
+    }
+
+    /// Simulates a single tick, unless the game stands still because it's
+    /// paused or edited
+    pub fn advance(&mut self) -> bool {
+        if self.paused || self.state == Editing {
+            return false;
+        }
+        self.step();
+        true
+    }
+
+    /// Simulates the game in steps of the given frequency, independent of
+    /// the frame rate
+    pub fn set_tick_rate(&mut self, ticks_per_second: u32) {
+        self.tick = 1.0 / ticks_per_second as f64;
+        self.tick_accumulator = 0.0;
+    }
+
+    /// Animates cleared lines for the given seconds before the next
+    /// tetromino appears, no animation without a delay
+    pub fn set_line_clear_delay(&mut self, seconds: f64) {
+        // a replay holds the delay of its start, a change ends the recording
+        if seconds != self.line_clear_delay {
+            self.recording_ended = true;
+        }
+        self.line_clear_delay = seconds;
+    }
+
+    /// Turns the particles and the screen shake on or off
+    pub fn set_effects(&mut self, particles: bool, shake: bool) {
+        self.effects = Effects::new(particles, shake);
+    }
+
+    /// Draws a pattern on every block to tell the colors apart without
+    /// seeing them
+    pub fn set_patterns(&mut self, patterns: bool) {
+        self.patterns = patterns;
+    }
+
+    pub fn set_rotate_180_key(&mut self, key: Key) {
+        self.rotate_180_key = key;
+    }
+
+    /// Shows the lowest row of the vanishing zone as a half row above the board
+    pub fn set_partial_row(&mut self, show_partial_row: bool) {
+        self.show_partial_row = show_partial_row;
+    }
+
+    /// Fits the board and the side bar into a window of the given size,
+    /// centered and with tiles of whole pixels to keep the blocks crisp
+    pub fn resize(&mut self, window_size: [f64; 2]) {
+        let layout = [
+            (self.board.width() + SIDE_BAR_WIDTH) as f64 * TILE_SIZE,
+            self.layout_rows() as f64 * TILE_SIZE + self.partial_row_height(),
+        ];
+        let fit = (window_size[0] / layout[0]).min(window_size[1] / layout[1]);
+        self.scale = (TILE_SIZE * fit).floor().max(1.0) / TILE_SIZE;
+        self.origin = [
+            ((window_size[0] - layout[0] * self.scale) / 2.0).floor(),
+            ((window_size[1] - layout[1] * self.scale) / 2.0).floor(),
+        ];
+    }
+
+    fn partial_row_height(&self) -> f64 {
+        if self.show_partial_row {
+            PARTIAL_ROW_HEIGHT as f64
+        } else {
+            0.0
+        }
+    }
+
+    // the number of rows the side bar is laid out for
+    fn layout_rows(&self) -> usize {
+        self.board.height().max(LAYOUT_ROWS)
+    }
+
+    // the topmost row of the board which is rendered
+    fn first_shown_row(&self) -> usize {
+        if self.show_partial_row {
+            BUFFER_HEIGHT - 1
+        } else {
+            BUFFER_HEIGHT
+        }
+    }
+
+    // the vertical position of the board's first row within the window
+    fn field_offset(&self) -> f64 {
+        self.partial_row_height() - BUFFER_HEIGHT as f64 * TILE_SIZE
+    }
+
+    // the cell of the board below the given window coordinates
+    fn cell_at(&self, position: [f64; 2]) -> Option<(usize, usize)> {
+        let x = ((position[0] - self.origin[0]) / self.scale / TILE_SIZE).floor();
+        let y = ((position[1] - self.origin[1]) / self.scale - self.field_offset()) / TILE_SIZE;
+        let y = y.floor();
+        if x < 0.0
+            || y < self.first_shown_row() as f64
+            || x >= self.board.width() as f64
+            || y >= self.board.len() as f64
+        {
+            None
+        } else {
+            Some((x as usize, y as usize))
+        }
+    }
+
+    pub fn mouse_move(&mut self, position: [f64; 2]) {
+        let cell = self.cell_at(position);
+        if let Some(editor) = self.editor.as_mut() {
+            editor.mouse_move(cell);
+        }
+    }
+
+    pub fn mouse_press(&mut self, button: &MouseButton) {
+        if let Some(editor) = self.editor.as_mut() {
+            editor.mouse_press(button);
+        }
+    }
+
+    pub fn mouse_release(&mut self, _button: &MouseButton) {
+        if let Some(editor) = self.editor.as_mut() {
+            editor.mouse_release();
+        }
+    }
+
+    fn spawn(&mut self, shape: &'static Tetromino) {
+        self.active_tetromino = ActiveTetromino::spawn(shape, self.board.width(), self.buffer);
+        self.last_move_rotation = false;
+        self.finesse_inputs = 0;
+        self.update_hint();
+        // block out: the new tetromino overlaps the stack
+        if !self.active_tetromino.fits(&self.board) {
+            self.state = Defeated;
+        }
+    }
+
+    /// Sets the number of rows above the board the falling tetromino may
+    /// enter. With fewer than two rows tetrominoes spawn within the board.
+    pub fn set_buffer(&mut self, buffer: usize) {
+        self.buffer = buffer;
+        self.spawn(self.active_tetromino.shape());
+    }
+
+    /// Swaps the active tetromino with the held one, once per tetromino
+    fn hold(&mut self) {
+        if self.hold_used {
+            return;
+        }
+        let current = self.active_tetromino.shape();
+        self.state = Playing;
+        match self.hold {
+            Some(held) => self.spawn(held),
+            None => {
+                let Some(next_shape) = self.next_shape else {
+                    return;
+                };
+                self.spawn(next_shape);
+                self.next_shape = self.bag.next();
+            }
         }
+        self.hold = Some(current);
+        self.hold_used = true;
     }
 
-    pub fn create_board(initial_stack_size: usize) -> [[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT] {
-        let mut board = [[Default::default(); BOARD_WIDTH]; BOARD_HEIGHT];
+    // initial hold and rotation: the hold and rotate keys which are down
+    // while a tetromino spawns act before it appears, which may also save
+    // it from a block out
+    fn apply_initial_actions(&mut self) {
+        if self.control_state.hold {
+            self.hold();
+        }
+        let controls = &mut self.control_state;
+        let rotated = if controls.rotate_right.is_held() {
+            controls.rotate_right.reset();
+            self.active_tetromino.try_rotate_right(&self.board)
+        } else if controls.rotate_left.is_held() {
+            controls.rotate_left.reset();
+            self.active_tetromino.try_rotate_left(&self.board)
+        } else if controls.rotate_180.is_held() {
+            controls.rotate_180.reset();
+            self.active_tetromino.try_rotate_180(&self.board)
+        } else {
+            false
+        };
+        if rotated {
+            self.finesse_inputs += 1;
+            if self.state == Defeated {
+                self.state = Playing;
+            }
+        }
+    }
+
+    /// Starts from the given position. The queued tetrominoes are followed
+    /// by the usual random bags. Restarting returns to the position's board.
+    pub fn load_position(&mut self, position: Position) -> Result<(), String> {
+        let mut queue = position.queue;
+        if let Some(active) = position.active.as_ref() {
+            if !active.fits(&position.board) {
+                return Err("the active piece overlaps the board".to_string());
+            }
+            queue.insert(0, active.shape());
+        }
+        self.start_board = Some(position.board);
+        self.start_hold = position.hold;
+        self.bag = Box::new(FixedSequence::new(queue).followed_by_bag());
+        self.play_again();
+        if let Some(active) = position.active {
+            self.active_tetromino = active;
+            self.update_hint();
+        }
+        Ok(())
+    }
+
+    /// Starts over on the given board, also when restarting later on
+    pub fn load_board(&mut self, board: Board) {
+        self.start_board = Some(board);
+        self.play_again();
+    }
+
+    // the endless game, neither a puzzle nor started from a given board
+    fn is_marathon(&self) -> bool {
+        self.puzzles.is_empty() && self.start_board.is_none() && !self.master
+    }
+
+    /// Stores a running endless game to resume it at the next start. Once the
+    /// game is lost the saved one is removed.
+    pub fn save(&self) -> std::io::Result<()> {
+        if !self.is_marathon() {
+            return Ok(());
+        }
+        match self.state {
+            Playing | Dropping | Waiting => SavedGame {
+                board: self.board.clone(),
+                active: self.active_tetromino.clone(),
+                next_shape: self.next_shape,
+                hold: self.hold,
+                hold_used: self.hold_used,
+                bag: self.bag.remaining(),
+                line_count: self.line_count,
+                tetromino_count: self.tetromino_count,
+                gravity_factor: self.gravity_factor,
+                gravity_accumulator: self.gravity_accumulator,
+                time: self.time,
+            }
+            .store(),
+            Defeated | Solved => savegame::remove(),
+            Editing => Ok(()),
+        }
+    }
+
+    /// Continues a saved endless game, paused until the player is ready
+    pub fn resume(&mut self, saved: SavedGame) -> Result<(), String> {
+        if (saved.board.width(), saved.board.height()) != (self.board.width(), self.board.height())
+        {
+            return Err(format!(
+                "the saved board is {}x{} but the game is played on {}x{}",
+                saved.board.width(),
+                saved.board.height(),
+                self.board.width(),
+                self.board.height()
+            ));
+        }
+        let active = saved.active.with_buffer(self.buffer);
+        if !active.fits(&saved.board) {
+            return Err("the active piece overlaps the board".to_string());
+        }
+        self.board = saved.board;
+        self.active_tetromino = active;
+        self.next_shape = saved.next_shape;
+        self.hold = saved.hold;
+        self.hold_used = saved.hold_used;
+        self.bag = Box::new(TetrominoBag::with_remaining(&saved.bag));
+        self.line_count = saved.line_count;
+        self.tetromino_count = saved.tetromino_count;
+        self.gravity_factor = saved.gravity_factor;
+        self.gravity_accumulator = saved.gravity_accumulator;
+        self.time = saved.time;
+        self.state = Playing;
+        self.ended = false;
+        self.paused = true;
+        self.update_hint();
+        self.history.clear();
+        if self.practice {
+            self.record_snapshot();
+        }
+        Ok(())
+    }
+
+    /// The visible rows with the falling tetromino and its ghost, and what
+    /// the side bar shows
+    pub fn frame(&self) -> Frame {
+        let first_row = self.first_shown_row();
+        let (board, clearing) = match self.line_clear.as_ref() {
+            Some(line_clear) => (&line_clear.board, line_clear.rows.as_slice()),
+            None => (&self.board, [].as_slice()),
+        };
+        let mut rows = board[first_row..].to_vec();
+        let mut ghost = None;
+        if let Playing | Dropping = self.state {
+            let mut landed = self.active_tetromino.clone();
+            while landed.try_move_down(&self.board) {}
+            let points = landed
+                .as_points()
+                .into_iter()
+                .filter(|&(_, y)| y >= first_row)
+                .map(|(x, y)| (x, y - first_row))
+                .collect();
+            ghost = Some((self.active_tetromino.get_color(), points));
+            for (x, y) in self.active_tetromino.as_points() {
+                if y >= first_row {
+                    rows[y - first_row][x] = Some(self.active_tetromino.get_color());
+                }
+            }
+        }
+        let status = match self.state {
+            _ if self.paused => Some("PAUSED"),
+            Defeated if self.puzzle().is_some() => Some("FAILED"),
+            Defeated => Some("GAME OVER"),
+            Solved => Some("SOLVED"),
+            Editing => Some("EDITING"),
+            Playing | Dropping | Waiting => None,
+        };
+        Frame {
+            rows,
+            partial_row: self.show_partial_row,
+            clearing: clearing
+                .iter()
+                .filter(|&&y| y >= first_row)
+                .map(|&y| y - first_row)
+                .collect(),
+            ghost,
+            next: self.next_shape,
+            hold: self.hold,
+            lines: self.line_count,
+            level: self.master.then_some(self.level),
+            puzzle: self.puzzle().map(|_| self.puzzle_index + 1),
+            status,
+            side_bar_color: self.side_bar_color(),
+            menu: None,
+        }
+    }
+
+    /// The game as a picture, drawn without a window
+    pub fn screenshot(&self) -> RgbaImage {
+        screenshot::render(&self.frame(), TILE_SIZE as u32)
+    }
+
+    // saves a screenshot in the data directory
+    fn save_screenshot(&self) -> Result<PathBuf, String> {
+        let seconds = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        let path = storage::data_file(&format!("screenshot-{}.png", seconds))
+            .map_err(|e| e.to_string())?;
+        self.screenshot()
+            .save(&path)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+        Ok(path)
+    }
+
+    // green once a puzzle is solved and red if failed
+    fn side_bar_color(&self) -> [f32; 4] {
+        let side_bar = theme::side_bar();
+        match (self.state, self.puzzle()) {
+            (Solved, _) => side_bar.solved,
+            (Defeated, Some(_)) => side_bar.failed,
+            _ => side_bar.normal,
+        }
+    }
+
+    pub fn to_text(&self) -> String {
+        let active = match self.state {
+            Playing | Dropping | Waiting => Some(&self.active_tetromino),
+            Defeated | Solved | Editing => None,
+        };
+        board_text::format(&self.board, active)
+    }
+
+    /// The current position as fumen data, only boards ten columns wide can
+    /// be encoded
+    pub fn to_fumen(&self) -> Result<String, String> {
+        let active = match self.state {
+            Playing | Dropping | Waiting => Some(&self.active_tetromino),
+            Defeated | Solved | Editing => None,
+        };
+        let queue: Vec<&'static Tetromino> = self.next_shape.into_iter().collect();
+        fumen::encode(&self.board, active, &queue, self.hold)
+    }
+
+    /// Switches to puzzle mode starting with the puzzle at `index`.
+    /// Once solved the player may advance to the next one.
+    pub fn load_puzzles(&mut self, puzzles: Vec<Puzzle>, index: usize) {
+        self.puzzles = puzzles;
+        self.puzzle_index = index;
+        self.solved_puzzles = puzzle::solved_puzzles();
+        self.start_puzzle();
+    }
+
+    fn puzzle(&self) -> Option<&Puzzle> {
+        self.puzzles.get(self.puzzle_index)
+    }
+
+    fn start_puzzle(&mut self) {
+        if let Some(puzzle) = self.puzzle() {
+            let sequence = FixedSequence::new(puzzle.pieces.clone());
+            // puzzles without a goal are sandboxes which go on randomly
+            let bag: Box<dyn Randomizer> = match puzzle.goal {
+                Some(_) => Box::new(sequence),
+                None => Box::new(sequence.followed_by_bag()),
+            };
+            self.start_hold = puzzle.hold;
+            self.bag = bag;
+            self.play_again();
+        }
+    }
+
+    fn next_puzzle(&mut self) {
+        self.puzzle_index = (self.puzzle_index + 1) % self.puzzles.len();
+        self.start_puzzle();
+    }
+
+    /// Counts the occupied corners of the 3x3 box around the center of a T
+    /// tetromino. Corners outside of the board count as occupied.
+    fn t_spin_corners(&self, board: &Board) -> usize {
+        let points = self.active_tetromino.as_points();
+        let center = points.iter().find(|&&(x, y)| {
+            points
+                .iter()
+                .filter(|&&(x2, y2)| x.abs_diff(x2) + y.abs_diff(y2) == 1)
+                .count()
+                == 3
+        });
+        let Some(&(x, y)) = center else {
+            return 0;
+        };
+        [(-1, -1), (1, -1), (-1, 1), (1, 1)]
+            .into_iter()
+            .map(|(dx, dy)| (x as i32 + dx, y as i32 + dy))
+            .filter(|&(cx, cy)| {
+                cx < 0
+                    || cy < 0
+                    || board
+                        .get(cy as usize)
+                        .and_then(|row| row.get(cx as usize))
+                        .is_none_or(|cell| cell.is_some())
+            })
+            .count()
+    }
+
+    fn is_t_spin(&self, board: &Board) -> bool {
+        self.last_move_rotation
+            && self.active_tetromino.shape().is_standard()
+            && self.active_tetromino.shape().get_name() == 'T'
+            && self.t_spin_corners(board) >= 3
+    }
+
+    fn track_puzzle(&mut self, full_line_count: usize, t_spin: bool) {
+        let Some(goal) = self.puzzle().and_then(|p| p.goal) else {
+            return;
+        };
+        let progress = &mut self.puzzle_progress;
+        progress.lines += full_line_count;
+        progress.pieces += 1;
+        if t_spin && full_line_count == 2 {
+            progress.t_spin_doubles += 1;
+        }
+        if full_line_count > 0 && self.board.iter().flatten().all(|cell| cell.is_none()) {
+            progress.perfect_clear = true;
+        }
+        if goal.is_reached(progress) {
+            self.state = Solved;
+            let name = self.puzzles[self.puzzle_index].name.clone();
+            if let Err(e) = puzzle::mark_solved(&name) {
+                self.report(Message::Error(format!(
+                    "Could not store the solved puzzle: {}",
+                    e
+                )));
+            }
+            self.solved_puzzles.insert(name);
+        }
+    }
+
+    pub fn set_finesse(&mut self, finesse: bool) {
+        self.finesse = finesse;
+    }
+
+    fn report_finesse_stats(&mut self) {
+        let stats = self.finesse_stats;
+        self.report(Message::Info(format!(
+            "Finesse: {} faults in {} pieces ({} extra inputs)",
+            stats.faults, stats.pieces, stats.extra_inputs
+        )));
+    }
+
+    pub fn set_hint(&mut self, show_hint: bool) {
+        self.show_hint = show_hint;
+        self.update_hint();
+    }
+
+    pub fn set_coach(&mut self, coach: bool) {
+        self.coach = coach;
+        self.verdict = None;
+        self.flagged_points.clear();
+    }
+
+    fn update_hint(&mut self) {
+        self.hint = if self.show_hint {
+            evaluator::best_placement(&self.board, self.active_tetromino.shape(), self.buffer)
+        } else {
+            None
+        };
+    }
+
+    fn coach_placement(&mut self, board: &Board) {
+        let points = self.active_tetromino.as_points();
+        let verdict = evaluator::judge(board, &points, self.active_tetromino.shape(), self.buffer);
+        // follow the locked cells while the rows above cleared lines collapse
+        let full_rows: Vec<usize> = (0..self.board.len())
+            .filter(|&y| self.board[y].iter().all(|cell| cell.is_some()))
+            .collect();
+        self.flagged_points = if verdict.is_bad() {
+            points
+                .into_iter()
+                .filter(|(_, y)| !full_rows.contains(y))
+                .map(|(x, y)| (x, y + full_rows.iter().filter(|&&row| row > y).count()))
+                .collect()
+        } else {
+            Vec::new()
+        };
+        self.verdict = Some(verdict);
+    }
+
+    pub fn create_board(
+        initial_stack_size: usize,
+        width: usize,
+        height: usize,
+        rng: &mut impl Rng,
+    ) -> Board {
+        let mut board = Board::new(width, height);
+        let bottom = board.len() - 1;
         if initial_stack_size > 0 {
             for y in 0usize..initial_stack_size {
                 // set random cells within a row
-                for x in (0usize..BOARD_WIDTH).filter(|_| rand::rng().random()) {
-                    board[(BOARD_HEIGHT - 1) - y][x] = Some(Color::Grey);
+                for x in (0usize..width).filter(|_| rng.random()) {
+                    board[bottom - y][x] = Some(Color::Grey);
                 }
             }
         }
@@ -159,178 +1242,367 @@ impl Tetris {
     }
 
     fn print_digit(&mut self, digit: usize, x_offset: usize) {
+        let board = &mut self.board[BUFFER_HEIGHT..];
         match digit {
             0 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][0 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][0 + x_offset] = Some(Color::Grey);
-                self.board[3][2 + x_offset] = Some(Color::Grey);
-                self.board[4][0 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][0 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
-                self.board[5][2 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][0 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][0 + x_offset] = Some(Color::Grey);
+                board[3][2 + x_offset] = Some(Color::Grey);
+                board[4][0 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][0 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
+                board[5][2 + x_offset] = Some(Color::Grey);
             }
             1 => {
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][1 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][2 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][2 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][1 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][2 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][2 + x_offset] = Some(Color::Grey);
             }
             2 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[4][0 + x_offset] = Some(Color::Grey);
-                self.board[5][0 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
-                self.board[5][2 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[4][0 + x_offset] = Some(Color::Grey);
+                board[5][0 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
+                board[5][2 + x_offset] = Some(Color::Grey);
             }
             3 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][2 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
-                self.board[5][0 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][2 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
+                board[5][0 + x_offset] = Some(Color::Grey);
             }
             4 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[2][0 + x_offset] = Some(Color::Grey);
-                self.board[3][0 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[3][2 + x_offset] = Some(Color::Grey);
-                self.board[4][1 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[2][0 + x_offset] = Some(Color::Grey);
+                board[3][0 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[3][2 + x_offset] = Some(Color::Grey);
+                board[4][1 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
             }
             5 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][0 + x_offset] = Some(Color::Grey);
-                self.board[3][0 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[3][2 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
-                self.board[5][0 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][0 + x_offset] = Some(Color::Grey);
+                board[3][0 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[3][2 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
+                board[5][0 + x_offset] = Some(Color::Grey);
             }
             6 => {
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][0 + x_offset] = Some(Color::Grey);
-                self.board[3][0 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[4][0 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][0 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
-                self.board[5][2 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][0 + x_offset] = Some(Color::Grey);
+                board[3][0 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[4][0 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][0 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
+                board[5][2 + x_offset] = Some(Color::Grey);
             }
             7 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[4][1 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[4][1 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
             }
             8 => {
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[2][0 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[4][0 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[2][0 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[4][0 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
             }
             9 => {
-                self.board[1][0 + x_offset] = Some(Color::Grey);
-                self.board[1][1 + x_offset] = Some(Color::Grey);
-                self.board[1][2 + x_offset] = Some(Color::Grey);
-                self.board[2][0 + x_offset] = Some(Color::Grey);
-                self.board[2][2 + x_offset] = Some(Color::Grey);
-                self.board[3][0 + x_offset] = Some(Color::Grey);
-                self.board[3][1 + x_offset] = Some(Color::Grey);
-                self.board[3][2 + x_offset] = Some(Color::Grey);
-                self.board[4][2 + x_offset] = Some(Color::Grey);
-                self.board[5][0 + x_offset] = Some(Color::Grey);
-                self.board[5][1 + x_offset] = Some(Color::Grey);
-                self.board[5][2 + x_offset] = Some(Color::Grey);
+                board[1][0 + x_offset] = Some(Color::Grey);
+                board[1][1 + x_offset] = Some(Color::Grey);
+                board[1][2 + x_offset] = Some(Color::Grey);
+                board[2][0 + x_offset] = Some(Color::Grey);
+                board[2][2 + x_offset] = Some(Color::Grey);
+                board[3][0 + x_offset] = Some(Color::Grey);
+                board[3][1 + x_offset] = Some(Color::Grey);
+                board[3][2 + x_offset] = Some(Color::Grey);
+                board[4][2 + x_offset] = Some(Color::Grey);
+                board[5][0 + x_offset] = Some(Color::Grey);
+                board[5][1 + x_offset] = Some(Color::Grey);
+                board[5][2 + x_offset] = Some(Color::Grey);
             }
             _ => {}
         }
     }
 
     fn show_result(&mut self) {
-        self.board = [[Default::default(); BOARD_WIDTH]; BOARD_HEIGHT];
+        self.board = self.board.cleared();
+        // the digits need eight columns
+        if self.board.width() < 8 {
+            return;
+        }
         let first_digit = self.line_count % 10;
         self.print_digit(first_digit, 5);
         let second_digit = (self.line_count - first_digit) / 10;
         self.print_digit(second_digit, 1);
     }
 
+    fn timing(&self) -> Timing {
+        if self.master {
+            speed::master(self.level)
+        } else {
+            speed::normal(self.gravity_factor, self.line_clear_delay)
+        }
+    }
+
+    // rows per second the tetromino falls, faster while soft dropping
+    fn fall_speed(&self) -> f64 {
+        let mut timing = self.timing();
+        if self.state != Dropping {
+            return timing.rows_per_second();
+        }
+        if self.master {
+            timing.gravity = timing.gravity.max(speed::SOFT_DROP_GRAVITY);
+        }
+        timing.rows_per_second() * speed::SOFT_DROP_FACTOR
+    }
+
+    // the part of the next row the falling tetromino is drawn into, from the
+    // gravity accumulated so far and the time since the last tick
+    fn fall_offset(&self) -> f64 {
+        if self.paused || self.active_tetromino.is_grounded(&self.board) {
+            return 0.0;
+        }
+        (self.gravity_accumulator + self.tick_accumulator * self.fall_speed()).min(1.0)
+    }
+
     fn gravity(&mut self, amount: f64) {
-        self.gravity_accumulator += amount * self.gravity_factor;
-        if self.gravity_accumulator >= 0.35 {
-            self.gravity_accumulator = 0.0;
-            if !self.active_tetromino.try_move_down(&self.board) {
-                for &(x, y) in self.active_tetromino.as_points().iter() {
-                    if y < self.board.len() && x < self.board[y].len() {
-                        self.board[y][x] = Some(self.active_tetromino.get_color());
-                    } else {
-                        self.state = Defeated;
-                    }
-                }
-                if self.state == Playing || self.state == Dropping {
-                    self.state = Playing;
-                    let mut board: [[Option<Color>; BOARD_WIDTH]; BOARD_HEIGHT] =
-                        [[None; BOARD_WIDTH]; BOARD_HEIGHT];
-                    let mut full_line_count = BOARD_HEIGHT;
-                    for (new, old) in board.iter_mut().rev().zip(
-                        self.board
-                            .iter()
-                            .rev()
-                            .filter(|row| row.iter().any(|color| color.is_none())),
-                    ) {
-                        *new = *old;
-                        full_line_count -= 1;
-                    }
-                    self.board = board;
-                    self.line_count += full_line_count;
-                    self.active_tetromino = ActiveTetromino::new(self.next_shape);
-                    self.next_shape = self.bag.next().unwrap();
-                    self.tetromino_count += 1;
-                    if self.tetromino_count >= 10 {
-                        self.tetromino_count = 0;
-                        self.gravity_factor *= 1.1;
-                    }
+        let timing = self.timing();
+        self.gravity_accumulator += amount * self.fall_speed();
+        // at high gravity the tetromino falls several rows within one update
+        let mut landed = false;
+        while self.gravity_accumulator >= 1.0 {
+            if self.active_tetromino.try_move_down(&self.board) {
+                self.gravity_accumulator -= 1.0;
+                self.last_move_rotation = false;
+                self.lock_timer = 0.0;
+            } else {
+                self.gravity_accumulator = 0.0;
+                landed = true;
+            }
+        }
+        let lock = match timing.lock_delay {
+            None => landed,
+            // soft dropping locks a landed tetromino right away
+            Some(delay) if self.active_tetromino.is_grounded(&self.board) => {
+                self.lock_timer += amount;
+                self.state == Dropping || self.lock_timer >= delay
+            }
+            Some(_) => false,
+        };
+        if lock {
+            self.lock();
+        }
+    }
+
+    fn lock(&mut self) {
+        self.lock_timer = 0.0;
+        let board_before_lock = self.board.clone();
+        for &(x, y) in self.active_tetromino.as_points().iter() {
+            self.board[y][x] = Some(self.active_tetromino.get_color());
+        }
+        // lock out: the whole tetromino lies within the vanishing zone
+        if self.active_tetromino.is_above_visible_rows() {
+            self.state = Defeated;
+        }
+        if self.state == Playing || self.state == Dropping {
+            self.state = Playing;
+            if self.coach {
+                self.coach_placement(&board_before_lock);
+            }
+            if self.finesse
+                && let Some(minimum) =
+                    finesse::minimal_inputs(&self.active_tetromino, &board_before_lock)
+            {
+                self.finesse_stats.record(self.finesse_inputs, minimum);
+            }
+            let t_spin = self.is_t_spin(&board_before_lock);
+            let board_before_clear = self.board.clone();
+            let full_line_count = self.board.clear_lines();
+            let rows: Vec<usize> = (0..board_before_clear.len())
+                .filter(|&y| board_before_clear[y].iter().all(|cell| cell.is_some()))
+                .collect();
+            self.effects.sparks(
+                &self.active_tetromino.as_points(),
+                self.active_tetromino.get_color().as_rgba(),
+            );
+            if full_line_count == 4 || (t_spin && full_line_count > 0) {
+                self.effects.burst(&board_before_clear, &rows);
+            }
+            let duration = self.timing().line_clear;
+            if full_line_count > 0 && duration > 0.0 {
+                self.line_clear = Some(LineClear {
+                    board: board_before_clear,
+                    rows,
+                    duration,
+                    remaining: duration,
+                });
+            }
+            self.line_count += full_line_count;
+            self.track_puzzle(full_line_count, t_spin);
+            if self.master && full_line_count > 0 {
+                self.level = speed::next_master_level(self.level, Some(full_line_count));
+                if self.level >= speed::MASTER_LEVELS {
+                    self.state = Solved;
                 }
             }
+            if self.state == Solved {
+                return;
+            }
+            let Some(next_shape) = self.next_shape else {
+                // a puzzle ran out of pieces
+                self.state = Defeated;
+                return;
+            };
+            let timing = self.timing();
+            self.spawn(next_shape);
+            self.next_shape = self.bag.next();
+            self.hold_used = false;
+            self.tetromino_count += 1;
+            if self.master {
+                self.level = speed::next_master_level(self.level, None);
+            } else if self.tetromino_count >= 10 {
+                self.tetromino_count = 0;
+                self.gravity_factor *= 1.1;
+            }
+            // the next tetromino appears after the ARE and line clear delay
+            let delay = if full_line_count > 0 {
+                timing.are + timing.line_clear
+            } else {
+                timing.are
+            };
+            if delay > 0.0 && self.state == Playing {
+                self.state = Waiting;
+                self.spawn_delay = delay;
+            } else {
+                self.apply_initial_actions();
+            }
+            if self.practice && (self.state == Playing || self.state == Waiting) {
+                self.record_snapshot();
+            }
+        }
+    }
+
+    // plays the same game again, a recorded game is recorded anew with a new
+    // seed since a restart isn't played back
+    fn restart(&mut self) {
+        if self.recording.is_some() && !self.recording_ended {
+            self.start_recording();
+        } else {
+            self.play_again();
+        }
+    }
+
+    /// Lets go of the keys held down as the menu opens, as if they were
+    /// released, so they don't keep acting once the game continues
+    pub fn release_controls(&mut self) {
+        let held = [
+            (self.state == Dropping, Key::Down),
+            (self.control_state.hold, Key::C),
+            (self.control_state.rotate_right.is_held(), Key::E),
+            (self.control_state.rotate_left.is_held(), Key::Up),
+            (self.control_state.move_left.is_held(), Key::Left),
+            (self.control_state.move_right.is_held(), Key::Right),
+            (self.control_state.rotate_180.is_held(), self.rotate_180_key),
+        ];
+        for (_, key) in held.iter().filter(|(held, _)| *held) {
+            self.key_release(key);
         }
     }
 
     fn play_again(&mut self) {
         self.state = Playing;
+        self.ended = false;
         self.gravity_accumulator = 0.0;
+        self.lock_timer = 0.0;
+        self.line_clear = None;
+        self.effects.clear();
         self.tetromino_count = 0;
         self.line_count = 0;
-        self.gravity_factor = 0.5;
-        self.board = Tetris::create_board(self.initial_stack_size);
+        // every level above the first is one speed up of the endless game
+        if self.master {
+            self.gravity_factor = 0.5;
+            self.level = self.start_level;
+        } else {
+            self.gravity_factor = 0.5 * 1.1f64.powi(self.start_level.saturating_sub(1) as i32);
+            self.level = 0;
+        }
+        let (width, height) = (self.board.width(), self.board.height());
+        self.board = match (self.puzzle(), &self.start_board) {
+            (Some(puzzle), _) => puzzle.board.clone(),
+            (None, Some(board)) => board.clone(),
+            (None, None) => {
+                Tetris::create_board(self.initial_stack_size, width, height, &mut self.rng)
+            }
+        };
         self.bag.clear();
-        self.active_tetromino = ActiveTetromino::new(self.bag.next().unwrap());
-        self.next_shape = self.bag.next().unwrap();
+        self.hold = self.start_hold;
+        self.hold_used = false;
+        self.puzzle_progress = Progress::default();
+        self.verdict = None;
+        self.flagged_points.clear();
+        self.finesse_stats = FinesseStats::default();
+        let shape = self.bag.next().unwrap();
+        self.spawn(shape);
+        self.next_shape = self.bag.next();
+        self.history.clear();
+        if self.practice {
+            self.record_snapshot();
+        }
+    }
+
+    // draws the background picture of the theme and returns the context of
+    // the layout, nothing is drawn without a skin
+    fn render_background<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
+        &self,
+        c: &Context,
+        g: &mut G,
+    ) -> Option<Context> {
+        let skin = self.skin.as_ref()?;
+        let c = c.trans(self.origin[0], self.origin[1]).zoom(self.scale);
+        if let Some(background) = skin.background.as_ref() {
+            Image::new()
+                .rect([
+                    0.0,
+                    0.0,
+                    (self.board.width() + SIDE_BAR_WIDTH) as f64 * TILE_SIZE,
+                    self.layout_rows() as f64 * TILE_SIZE + self.partial_row_height(),
+                ])
+                .draw(background, &Default::default(), c.transform, g);
+        }
+        Some(c)
     }
 
     pub fn render<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
@@ -338,66 +1610,353 @@ impl Tetris {
         c: &Context,
         g: &mut G,
     ) {
-        let c = c.zoom(self.scale);
+        let (Some(skin), Some(c)) = (self.skin.as_ref(), self.render_background(c, g)) else {
+            return;
+        };
         fn pos(n: usize) -> f64 {
             n as f64 * TILE_SIZE
         }
-        // render the board
-        for y in 0usize..BOARD_HEIGHT {
-            for x in 0usize..BOARD_WIDTH {
-                if let Some(e) = self.board[y][x].as_ref() {
-                    Image::new_color(e.as_rgba()).draw(
-                        &self.block,
-                        &Default::default(),
-                        c.trans(pos(x), pos(y)).transform,
-                        g,
-                    )
+        // render the board, the rows of the vanishing zone are hidden
+        let (shake_x, shake_y) = self.effects.offset();
+        let field = c.trans(
+            shake_x * TILE_SIZE,
+            self.field_offset() + shake_y * TILE_SIZE,
+        );
+        let first_row = self.first_shown_row();
+        let shown = |&&(_, y): &&(usize, usize)| y >= first_row;
+        // cleared rows light up and fade out before the rows above collapse
+        let (board, clearing) = match (self.editor.as_ref(), self.line_clear.as_ref()) {
+            (Some(editor), _) => (&editor.board, None),
+            (None, Some(line_clear)) => (&line_clear.board, Some(line_clear)),
+            (None, None) => (&self.board, None),
+        };
+        for (y, row) in board.iter().enumerate().skip(first_row) {
+            let fading = clearing
+                .filter(|line_clear| line_clear.rows.contains(&y))
+                .map(|line_clear| [1.0, 1.0, 1.0, 1.0 - line_clear.progress() as f32]);
+            for (x, cell) in row.iter().enumerate() {
+                if let Some(&e) = cell.as_ref() {
+                    let transform = field.trans(pos(x), pos(y)).transform;
+                    match fading {
+                        Some(fading) => {
+                            Image::new_color(fading)
+                                .rect([0.0, 0.0, TILE_SIZE, TILE_SIZE])
+                                .draw(skin.block(e).0, &Default::default(), transform, g);
+                        }
+                        None => self.draw_block(e, transform, g),
+                    }
                 };
             }
         }
-        if self.state != Defeated {
-            for &(x, y) in self.active_tetromino.as_points().iter() {
-                Image::new_color(self.active_tetromino.get_color().as_rgba()).draw(
-                    &self.block,
+        if self.state == Playing || self.state == Dropping {
+            if let Some(hint) = self.hint.as_ref() {
+                let outline =
+                    Rectangle::new_border(self.active_tetromino.get_color().as_rgba(), 2.0);
+                for &(x, y) in hint.points.iter().filter(shown) {
+                    outline.draw(
+                        [2.0, 2.0, TILE_SIZE - 4.0, TILE_SIZE - 4.0],
+                        &Default::default(),
+                        field.trans(pos(x), pos(y)).transform,
+                        g,
+                    );
+                }
+            }
+            let outline = Rectangle::new_border(Color::Red.as_rgba(), 3.0);
+            for &(x, y) in self.flagged_points.iter().filter(shown) {
+                outline.draw(
+                    [3.0, 3.0, TILE_SIZE - 6.0, TILE_SIZE - 6.0],
                     &Default::default(),
-                    c.trans(pos(x), pos(y)).transform,
+                    field.trans(pos(x), pos(y)).transform,
+                    g,
+                );
+            }
+            let fall = self.fall_offset() * TILE_SIZE;
+            for &(x, y) in self.active_tetromino.as_points().iter().filter(shown) {
+                self.draw_block(
+                    self.active_tetromino.get_color(),
+                    field.trans(pos(x), pos(y) + fall).transform,
                     g,
                 );
             }
         }
-        // render the side bar
+        self.effects
+            .render(skin.plain_block(), TILE_SIZE, first_row, &field, g);
+        // render the side bar, green once a puzzle is solved and red if failed
         rectangle(
-            Color::Grey.as_rgba(),
+            self.side_bar_color(),
             [
                 0.0,
                 0.0,
-                WINDOW_WIDTH as f64 - pos(BOARD_WIDTH),
-                WINDOW_HEIGHT as f64,
+                pos(SIDE_BAR_WIDTH),
+                pos(self.layout_rows()) + self.partial_row_height(),
             ], // rectangle
-            c.trans(pos(BOARD_WIDTH), 0.0).transform,
+            c.trans(pos(self.board.width()), 0.0).transform,
             g,
         );
-        for &(x, y) in self.next_shape.points(Rotation::R0).iter() {
-            Image::new_color(self.next_shape.get_color().as_rgba()).draw(
-                &self.block,
+        if let Some(editor) = self.editor.as_ref() {
+            self.render_editor(editor, &c, g);
+            return;
+        }
+        if let Some(next_shape) = self.next_shape {
+            self.render_shape(
+                next_shape,
+                &c.trans(pos(self.board.width()) + pos(1), 0.0),
+                g,
+            );
+        }
+        if let Some(hold) = self.hold {
+            self.render_shape(hold, &c.trans(pos(self.board.width()) + pos(1), pos(12)), g);
+        }
+        // render the level of the master mode
+        if self.master {
+            hud::draw_number(
+                self.level,
+                Color::Cyan.as_rgba(),
+                TILE_SIZE / 5.0,
+                c.trans(
+                    pos(self.board.width()) + pos(1),
+                    pos(self.layout_rows() - 2),
+                )
+                .transform,
+                self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                g,
+            );
+        }
+        // render the number of the puzzle, green if solved before
+        if let Some(puzzle) = self.puzzle() {
+            let color = if self.solved_puzzles.contains(&puzzle.name) {
+                Color::Lime
+            } else {
+                Color::Cyan
+            };
+            let goal = puzzle.goal;
+            hud::draw_number(
+                self.puzzle_index + 1,
+                color.as_rgba(),
+                TILE_SIZE / 5.0,
+                c.trans(
+                    pos(self.board.width()) + pos(1),
+                    pos(self.layout_rows() - 2),
+                )
+                .transform,
+                self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                g,
+            );
+            if let Some(Goal::Survive(pieces)) = goal {
+                hud::draw_number(
+                    pieces.saturating_sub(self.puzzle_progress.pieces),
+                    Color::Cyan.as_rgba(),
+                    TILE_SIZE / 5.0,
+                    c.trans(
+                        pos(self.board.width()) + pos(3),
+                        pos(self.layout_rows() - 2),
+                    )
+                    .transform,
+                    self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                    g,
+                );
+            }
+        }
+        // render the coach's verdict: holes, overhangs and stack height
+        if let Some(verdict) = self.verdict {
+            let faults = [
+                verdict.created_holes,
+                verdict.created_overhangs,
+                verdict.raised_height,
+            ];
+            for (i, &fault) in faults.iter().enumerate() {
+                let color = if fault { Color::Red } else { Color::Lime };
+                rectangle(
+                    color.as_rgba(),
+                    [0.0, 0.0, TILE_SIZE / 2.0, TILE_SIZE / 2.0],
+                    c.trans(pos(self.board.width()) + pos(i + 1), pos(5))
+                        .transform,
+                    g,
+                );
+            }
+        }
+        // render the finesse faults and the inputs of the last placement
+        if self.finesse {
+            let stats = &self.finesse_stats;
+            let cell = TILE_SIZE / 5.0;
+            let color = if stats.last_inputs > stats.last_minimum {
+                Color::Red
+            } else {
+                Color::Lime
+            };
+            hud::draw_number(
+                stats.faults,
+                Color::Red.as_rgba(),
+                cell,
+                c.trans(pos(self.board.width()) + pos(1), pos(7)).transform,
+                self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                g,
+            );
+            hud::draw_number(
+                stats.last_inputs,
+                color.as_rgba(),
+                cell,
+                c.trans(pos(self.board.width()) + pos(1), pos(9)).transform,
+                self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                g,
+            );
+            hud::draw_number(
+                stats.last_minimum,
+                Color::Lime.as_rgba(),
+                cell,
+                c.trans(pos(self.board.width()) + pos(3), pos(9)).transform,
+                self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                g,
+            );
+        }
+    }
+
+    /// Draws a screen of the menu instead of the game, laid out like the game
+    pub fn render_menu<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
+        &mut self,
+        view: &menu::View,
+        c: &Context,
+        g: &mut G,
+    ) {
+        let Some(c) = self.render_background(c, g) else {
+            return;
+        };
+        for label in view.layout(self.board.width() + SIDE_BAR_WIDTH) {
+            hud::draw_text(
+                &label.text,
+                label.color.as_rgba(),
+                label.cell * TILE_SIZE,
+                c.trans(label.x * TILE_SIZE, label.y * TILE_SIZE).transform,
+                self.skin.as_mut().and_then(|skin| skin.font.as_mut()),
+                g,
+            );
+        }
+    }
+
+    fn render_shape<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
+        &self,
+        shape: &'static Tetromino,
+        c: &Context,
+        g: &mut G,
+    ) {
+        // larger pieces are shrunk to the four tiles of a tetromino
+        let points = shape.points(Rotation::R0);
+        let extent = points.iter().map(|&(x, y)| x.max(y) + 1).max().unwrap_or(0);
+        let c = c.zoom((4.0 / extent as f64).min(1.0));
+        for &(x, y) in points.iter() {
+            self.draw_block(
+                shape.get_color(),
+                c.trans(x as f64 * TILE_SIZE, y as f64 * TILE_SIZE)
+                    .transform,
+                g,
+            );
+        }
+    }
+
+    // a block of the theme, with the pattern of its color if enabled
+    fn draw_block<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
+        &self,
+        color: Color,
+        transform: Matrix2d,
+        g: &mut G,
+    ) {
+        let Some(skin) = self.skin.as_ref() else {
+            return;
+        };
+        let (texture, tint) = skin.block(color);
+        // theme textures of any size fill a tile
+        Image::new_color(tint)
+            .rect([0.0, 0.0, TILE_SIZE, TILE_SIZE])
+            .draw(texture, &Default::default(), transform, g);
+        if self.patterns {
+            hud::draw_pattern(color, TILE_SIZE, transform, g);
+        }
+    }
+
+    fn render_editor<G: Graphics<Texture = piston_window::wgpu_graphics::Texture>>(
+        &self,
+        editor: &Editor,
+        c: &Context,
+        g: &mut G,
+    ) {
+        fn pos(n: usize) -> f64 {
+            n as f64 * TILE_SIZE
+        }
+        let color = editor::PALETTE[editor.color].as_rgba();
+        if let Some((x, y)) = editor.cursor {
+            Rectangle::new_border(color, 2.0).draw(
+                [1.0, 1.0, TILE_SIZE - 2.0, TILE_SIZE - 2.0],
                 &Default::default(),
-                c.trans(pos(BOARD_WIDTH) + pos(x + 1), pos(y)).transform,
+                c.trans(pos(x), pos(y) + self.field_offset()).transform,
                 g,
             );
         }
+        // the first upcoming tetrominoes at half size
+        for (i, &shape) in editor.queue.iter().take(6).enumerate() {
+            let c = c.trans(pos(self.board.width()) + pos(1), i as f64 * 1.5 * TILE_SIZE);
+            self.render_shape(shape, &c.zoom(0.5), g);
+        }
+        if let Some(hold) = editor.hold {
+            self.render_shape(hold, &c.trans(pos(self.board.width()) + pos(1), pos(12)), g);
+        }
+        // the palette with the selected colour outlined
+        let size = TILE_SIZE / 2.0;
+        for (i, color) in editor::PALETTE.iter().enumerate() {
+            let c = c.trans(
+                pos(self.board.width()) + size * (i as f64 + 1.0),
+                pos(self.layout_rows() - 2),
+            );
+            rectangle(color.as_rgba(), [0.0, 0.0, size, size], c.transform, g);
+            if self.patterns {
+                hud::draw_pattern(*color, size, c.transform, g);
+            }
+            if i == editor.color {
+                Rectangle::new_border([1.0; 4], 2.0).draw(
+                    [0.0, 0.0, size, size],
+                    &Default::default(),
+                    c.transform,
+                    g,
+                );
+            }
+        }
     }
 
     pub fn update(&mut self, args: &UpdateArgs) {
-        if self.paused {
+        if self.paused || self.state == Editing {
+            self.tick_accumulator = 0.0;
             return;
         }
+        self.tick_accumulator += args.dt.min(MAX_FRAME_TIME);
+        while self.tick_accumulator >= self.tick {
+            self.tick_accumulator -= self.tick;
+            self.step();
+            if self.paused || self.state == Editing {
+                break;
+            }
+        }
+    }
 
-        self.time += args.dt;
+    // advances the game by one tick, keys pressed since the last one apply now
+    fn step(&mut self) {
+        self.tick_count += 1;
+        self.effects.update(self.tick);
+        if let Some(line_clear) = self.line_clear.as_mut() {
+            line_clear.remaining -= self.tick;
+            if line_clear.remaining <= 0.0 {
+                self.line_clear = None;
+            }
+        }
+        // inputs during the delay wait for the next tetromino
+        if self.state != Waiting {
+            self.time += self.tick;
+        }
 
-        if self.time > UPDATE_TIME {
+        if self.time > UPDATE_TIME && self.state != Waiting {
             if self.control_state.rotate_right.is_active() {
                 for _ in 0..self.control_state.rotate_right.press_count {
-                    self.active_tetromino.try_rotate_right(&self.board);
+                    if self.active_tetromino.try_rotate_right(&self.board) {
+                        self.last_move_rotation = true;
+                    }
                 }
 
                 self.control_state.rotate_right.update_by_time();
@@ -405,21 +1964,36 @@ impl Tetris {
 
             if self.control_state.rotate_left.is_active() {
                 for _ in 0..self.control_state.rotate_left.press_count {
-                    self.active_tetromino.try_rotate_left(&self.board);
+                    if self.active_tetromino.try_rotate_left(&self.board) {
+                        self.last_move_rotation = true;
+                    }
                 }
                 self.control_state.rotate_left.update_by_time();
             }
 
+            if self.control_state.rotate_180.is_active() {
+                for _ in 0..self.control_state.rotate_180.press_count {
+                    if self.active_tetromino.try_rotate_180(&self.board) {
+                        self.last_move_rotation = true;
+                    }
+                }
+                self.control_state.rotate_180.update_by_time();
+            }
+
             if self.control_state.move_left.is_active() {
                 for _ in 0..self.control_state.move_left.press_count {
-                    self.active_tetromino.try_move_left(&self.board);
+                    if self.active_tetromino.try_move_left(&self.board) {
+                        self.last_move_rotation = false;
+                    }
                 }
                 self.control_state.move_left.update_by_time();
             }
 
             if self.control_state.move_right.is_active() {
                 for _ in 0..self.control_state.move_right.press_count {
-                    self.active_tetromino.try_move_right(&self.board);
+                    if self.active_tetromino.try_move_right(&self.board) {
+                        self.last_move_rotation = false;
+                    }
                 }
                 self.control_state.move_right.update_by_time();
             }
@@ -428,47 +2002,157 @@ impl Tetris {
         }
 
         match self.state {
-            Playing => self.gravity(args.dt),
-            Dropping => self.gravity(0.12 + args.dt),
-            Defeated => self.show_result(),
+            Playing | Dropping => self.gravity(self.tick),
+            Waiting => {
+                self.spawn_delay -= self.tick;
+                if self.spawn_delay <= 0.0 {
+                    self.state = Playing;
+                    self.apply_initial_actions();
+                }
+            }
+            Defeated | Solved if !self.ended => self.end_game(),
+            Defeated | Solved | Editing => {}
+        }
+    }
+
+    // the keys which move, rotate, hold and drop the tetromino, all others
+    // either don't change how the game plays out or end its recording
+    fn is_gameplay_key(&self, key: &Key) -> bool {
+        matches!(
+            key,
+            Key::Left
+                | Key::A
+                | Key::Right
+                | Key::D
+                | Key::E
+                | Key::Up
+                | Key::Q
+                | Key::C
+                | Key::LShift
+                | Key::RShift
+                | Key::Space
+                | Key::Down
+                | Key::S
+        ) || *key == self.rotate_180_key
+    }
+
+    fn record_input(&mut self, key: &Key, pressed: bool) {
+        if self.paused || !self.is_gameplay_key(key) {
+            return;
+        }
+        if let Some(replay) = self.recording.as_mut()
+            && !self.recording_ended
+        {
+            replay.inputs.push(Input {
+                tick: self.tick_count,
+                key: *key,
+                pressed,
+            });
         }
     }
 
     pub fn drop_fully(&mut self) {
-        while self.active_tetromino.try_move_down(&self.board) {}
+        while self.active_tetromino.try_move_down(&self.board) {
+            self.last_move_rotation = false;
+        }
     }
 
     pub fn key_press(&mut self, key: &Key) {
+        self.record_input(key, true);
         match (self.state, key) {
-            (Defeated, _) => {
-                if key == &Key::F1 {
-                    self.play_again()
+            (_, &Key::N) if !self.puzzles.is_empty() => self.next_puzzle(),
+            (_, &Key::F8) => {
+                let text = self.to_text();
+                self.report(Message::Info(text.trim_end().to_string()))
+            }
+            (_, &Key::F12) => {
+                let message = match self.save_screenshot() {
+                    Ok(path) => Message::Info(format!("Saved a screenshot to {}", path.display())),
+                    Err(e) => Message::Error(format!("Could not save the screenshot: {}", e)),
+                };
+                self.report(message)
+            }
+            (_, &Key::F9) => {
+                let message = match self.to_fumen() {
+                    Ok(fumen) => Message::Info(fumen),
+                    Err(e) => Message::Error(format!("Could not export the position: {}", e)),
+                };
+                self.report(message)
+            }
+            (Editing, &Key::Return) => self.close_editor(),
+            (Editing, &Key::F7) => {
+                if let Err(e) = self.save_editor() {
+                    self.report(Message::Error(format!("Could not save the sandbox: {}", e)));
+                }
+            }
+            (Editing, _) => {
+                if let Some(editor) = self.editor.as_mut() {
+                    editor.key_press(key)
                 }
             }
-            (Playing, &Key::P) => self.paused = !self.paused,
-            (_, &Key::F1) => self.play_again(),
-            (_, &Key::E) if !self.paused => self.control_state.rotate_right.update_on_press(),
+            (_, &Key::Tab) => self.open_editor(),
+            (_, &Key::Z) if self.practice => self.undo(),
+            (_, &Key::Y) if self.practice => self.redo(),
+            (Defeated, &Key::F1) | (Solved, &Key::F1) => self.restart(),
+            (Defeated, _) | (Solved, _) => {}
+            (Playing, &Key::P) | (Waiting, &Key::P) => self.paused = !self.paused,
+            (_, &Key::F1) => self.restart(),
+            (_, &Key::F2) => self.set_hint(!self.show_hint),
+            (_, &Key::F3) => self.set_coach(!self.coach),
+            (_, &Key::C) | (_, &Key::LShift) | (_, &Key::RShift) if !self.paused => {
+                self.control_state.hold = true;
+                // a hold key pressed during the delay acts when the next piece appears
+                if self.state != Waiting {
+                    self.hold()
+                }
+            }
+            // the piece appearing next can't be dropped yet
+            (Waiting, &Key::Space) | (Waiting, &Key::Down) | (Waiting, &Key::S) => {}
+            (_, &Key::E) if !self.paused => {
+                self.finesse_inputs += 1;
+                self.control_state.rotate_right.update_on_press()
+            }
             (_, &Key::Space) if !self.paused => {
+                let falling = matches!(self.state, Playing | Dropping);
+                let top = self.active_tetromino.as_points()[0].1;
                 self.state = Dropping;
-                self.drop_fully()
+                self.drop_fully();
+                if falling {
+                    let points = self.active_tetromino.as_points();
+                    let color = self.active_tetromino.get_color().as_rgba();
+                    self.effects.trail(&points, points[0].1 - top, color);
+                }
             }
             (_, &Key::Up) | (_, &Key::Q) if !self.paused => {
+                self.finesse_inputs += 1;
                 self.control_state.rotate_left.update_on_press()
             }
             (_, &Key::Left) | (_, &Key::A) if !self.paused => {
+                self.finesse_inputs += 1;
                 self.control_state.move_left.update_on_press()
             }
             (_, &Key::Right) | (_, &Key::D) if !self.paused => {
+                self.finesse_inputs += 1;
                 self.control_state.move_right.update_on_press()
             }
             (_, &Key::Down) | (_, &Key::S) if !self.paused => self.state = Dropping,
+            // the configurable key comes last so it never shadows a fixed one
+            (_, key) if *key == self.rotate_180_key && !self.paused => {
+                self.finesse_inputs += 1;
+                self.control_state.rotate_180.update_on_press()
+            }
             _ => {}
         }
     }
 
     pub fn key_release(&mut self, key: &Key) {
+        self.record_input(key, false);
         match (self.state, key) {
+            (Editing, _) => {}
             (Dropping, &Key::Down) | (Dropping, &Key::S) if !self.paused => self.state = Playing,
+            (_, &Key::C) | (_, &Key::LShift) | (_, &Key::RShift) if !self.paused => {
+                self.control_state.hold = false
+            }
             (_, &Key::E) if !self.paused => self.control_state.rotate_right.update_on_release(),
             (_, &Key::Up) | (_, &Key::Q) if !self.paused => {
                 self.control_state.rotate_left.update_on_release()
@@ -479,7 +2163,53 @@ impl Tetris {
             (_, &Key::Right) | (_, &Key::D) if !self.paused => {
                 self.control_state.move_right.update_on_release()
             }
+            (_, key) if *key == self.rotate_180_key && !self.paused => {
+                self.control_state.rotate_180.update_on_release()
+            }
             _ => {}
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // plays until the given tick, dropping a tetromino every half second
+    // until `last_input` and pushing it to the left every other time
+    fn play(game: &mut Tetris, last_input: u64, until: u64) {
+        while game.tick_count() < until {
+            let tick = game.tick_count();
+            if tick <= last_input {
+                match tick % 60 {
+                    0 => game.key_press(&Key::Left),
+                    10 => game.key_release(&Key::Left),
+                    30 => game.key_press(&Key::Space),
+                    31 => game.key_release(&Key::Space),
+                    _ => {}
+                }
+            }
+            game.advance();
+        }
+    }
+
+    #[test]
+    fn ends_the_recording_when_the_line_clear_delay_changes() {
+        let mut game = Tetris::new(None, 4, 10, 20);
+        game.set_high_scores(false);
+        game.set_line_clear_delay(0.3);
+        game.start_recording();
+        // the replay plays on for two seconds after its last input
+        play(&mut game, 480, 480 + 2 * speed::DEFAULT_TICK_RATE as u64);
+        let played = game.to_text();
+        game.set_line_clear_delay(0.0);
+        play(&mut game, 1200, 1200);
+
+        let replay = game.recording.take().unwrap();
+        assert_eq!(replay.line_clear_delay, 0.3);
+        assert_eq!(replay.inputs.last().map(|input| input.tick), Some(480));
+        let mut again = replay.start(None);
+        replay.play(&mut again, 1, |_| Ok(())).unwrap();
+        assert_eq!(again.to_text(), played);
+    }
+}
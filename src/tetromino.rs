@@ -1,9 +1,13 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use rand::seq::SliceRandom;
 
+use crate::piece_set;
 use crate::tetromino::Color::*;
 use crate::tetromino::Rotation::*;
+use crate::theme;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Debug)]
 pub enum Color {
     Cyan,
     Blue,
@@ -16,97 +20,154 @@ pub enum Color {
 }
 
 impl Color {
+    /// The color in the palette of the selected theme
     pub fn as_rgba(&self) -> [f32; 4] {
-        match *self {
-            Cyan => [0.0, 1.0, 1.0, 1.0],
-            Blue => [0.0, 0.5, 1.0, 1.0],
-            Orange => [1.0, 0.6, 0.0, 1.0],
-            Yellow => [1.0, 1.0, 0.0, 1.0],
-            Lime => [0.5, 1.0, 0.0, 1.0],
-            Purple => [0.8, 0.0, 1.0, 1.0],
-            Red => [1.0, 0.0, 0.0, 1.0],
-            Grey => [0.3, 0.3, 0.3, 1.0],
+        theme::palette(*self)
+    }
+
+    pub fn from_name(name: &str) -> Option<Color> {
+        match name.to_ascii_lowercase().as_str() {
+            "cyan" => Some(Cyan),
+            "blue" => Some(Blue),
+            "orange" => Some(Orange),
+            "yellow" => Some(Yellow),
+            "lime" => Some(Lime),
+            "purple" => Some(Purple),
+            "red" => Some(Red),
+            "grey" => Some(Grey),
+            _ => None,
         }
     }
 }
 
+/// A piece of the selected piece set. Despite the name it may consist of
+/// any number of blocks.
 pub struct Tetromino {
+    name: char,
     color: Color,
-    points: [[(usize, usize); 4]; 4],
+    points: [&'static [(usize, usize)]; 4],
+    spawn: (i32, i32),
 }
 
 impl Tetromino {
-    pub fn points(&self, rotation: Rotation) -> &[(usize, usize); 4] {
-        &self.points[rotation as usize]
+    /// A piece with the blocks of each rotation state, spawned moved by the
+    /// given columns and rows
+    pub fn new(
+        name: char,
+        color: Color,
+        points: [&'static [(usize, usize)]; 4],
+        spawn: (i32, i32),
+    ) -> Tetromino {
+        Tetromino {
+            name,
+            color,
+            points,
+            spawn,
+        }
+    }
+    pub fn points(&self, rotation: Rotation) -> &'static [(usize, usize)] {
+        self.points[rotation as usize]
+    }
+    /// Columns and rows the piece is moved from the spawn position of the
+    /// standard tetrominoes
+    pub fn spawn_offset(&self) -> (i32, i32) {
+        self.spawn
     }
     pub fn get_color(&self) -> Color {
         self.color
     }
+    pub fn get_name(&self) -> char {
+        self.name
+    }
+    /// The piece of the selected piece set with the given name
+    pub fn from_name(name: char) -> Option<&'static Tetromino> {
+        piece_set::shapes()
+            .iter()
+            .find(|t| t.name == name.to_ascii_uppercase())
+    }
+    /// Whether this is one of the seven standard tetrominoes
+    pub fn is_standard(&self) -> bool {
+        SHAPES.iter().any(|shape| std::ptr::eq(shape, self))
+    }
 }
 
 pub static SHAPES: [Tetromino; 7] = [
     Tetromino {
+        name: 'I',
         color: Cyan,
         points: [
-            [(0, 2), (1, 2), (2, 2), (3, 2)],
-            [(2, 0), (2, 1), (2, 2), (2, 3)],
-            [(0, 2), (1, 2), (2, 2), (3, 2)],
-            [(2, 0), (2, 1), (2, 2), (2, 3)],
+            &[(0, 2), (1, 2), (2, 2), (3, 2)],
+            &[(2, 0), (2, 1), (2, 2), (2, 3)],
+            &[(0, 2), (1, 2), (2, 2), (3, 2)],
+            &[(2, 0), (2, 1), (2, 2), (2, 3)],
         ],
+        spawn: (0, 0),
     },
     Tetromino {
+        name: 'J',
         color: Blue,
         points: [
-            [(0, 1), (1, 1), (2, 1), (2, 2)],
-            [(1, 0), (1, 1), (0, 2), (1, 2)],
-            [(0, 0), (0, 1), (1, 1), (2, 1)],
-            [(1, 0), (2, 0), (1, 1), (1, 2)],
+            &[(0, 1), (1, 1), (2, 1), (2, 2)],
+            &[(1, 0), (1, 1), (0, 2), (1, 2)],
+            &[(0, 0), (0, 1), (1, 1), (2, 1)],
+            &[(1, 0), (2, 0), (1, 1), (1, 2)],
         ],
+        spawn: (0, 0),
     },
     Tetromino {
+        name: 'L',
         color: Orange,
         points: [
-            [(0, 1), (1, 1), (2, 1), (0, 2)],
-            [(0, 0), (1, 0), (1, 1), (1, 2)],
-            [(2, 0), (0, 1), (1, 1), (2, 1)],
-            [(1, 0), (1, 1), (1, 2), (2, 2)],
+            &[(0, 1), (1, 1), (2, 1), (0, 2)],
+            &[(0, 0), (1, 0), (1, 1), (1, 2)],
+            &[(2, 0), (0, 1), (1, 1), (2, 1)],
+            &[(1, 0), (1, 1), (1, 2), (2, 2)],
         ],
+        spawn: (0, 0),
     },
     Tetromino {
+        name: 'O',
         color: Yellow,
         points: [
-            [(1, 1), (2, 1), (1, 2), (2, 2)],
-            [(1, 1), (2, 1), (1, 2), (2, 2)],
-            [(1, 1), (2, 1), (1, 2), (2, 2)],
-            [(1, 1), (2, 1), (1, 2), (2, 2)],
+            &[(1, 1), (2, 1), (1, 2), (2, 2)],
+            &[(1, 1), (2, 1), (1, 2), (2, 2)],
+            &[(1, 1), (2, 1), (1, 2), (2, 2)],
+            &[(1, 1), (2, 1), (1, 2), (2, 2)],
         ],
+        spawn: (0, 0),
     },
     Tetromino {
+        name: 'S',
         color: Lime,
         points: [
-            [(1, 1), (2, 1), (0, 2), (1, 2)],
-            [(1, 0), (1, 1), (2, 1), (2, 2)],
-            [(1, 1), (2, 1), (0, 2), (1, 2)],
-            [(1, 0), (1, 1), (2, 1), (2, 2)],
+            &[(1, 1), (2, 1), (0, 2), (1, 2)],
+            &[(1, 0), (1, 1), (2, 1), (2, 2)],
+            &[(1, 1), (2, 1), (0, 2), (1, 2)],
+            &[(1, 0), (1, 1), (2, 1), (2, 2)],
         ],
+        spawn: (0, 0),
     },
     Tetromino {
+        name: 'T',
         color: Purple,
         points: [
-            [(0, 1), (1, 1), (2, 1), (1, 2)],
-            [(1, 0), (0, 1), (1, 1), (1, 2)],
-            [(1, 0), (0, 1), (1, 1), (2, 1)],
-            [(1, 0), (1, 1), (2, 1), (1, 2)],
+            &[(0, 1), (1, 1), (2, 1), (1, 2)],
+            &[(1, 0), (0, 1), (1, 1), (1, 2)],
+            &[(1, 0), (0, 1), (1, 1), (2, 1)],
+            &[(1, 0), (1, 1), (2, 1), (1, 2)],
         ],
+        spawn: (0, 0),
     },
     Tetromino {
+        name: 'Z',
         color: Red,
         points: [
-            [(0, 1), (1, 1), (1, 2), (2, 2)],
-            [(2, 0), (1, 1), (2, 1), (1, 2)],
-            [(0, 1), (1, 1), (1, 2), (2, 2)],
-            [(2, 0), (1, 1), (2, 1), (1, 2)],
+            &[(0, 1), (1, 1), (1, 2), (2, 2)],
+            &[(2, 0), (1, 1), (2, 1), (1, 2)],
+            &[(0, 1), (1, 1), (1, 2), (2, 2)],
+            &[(2, 0), (1, 1), (2, 1), (1, 2)],
         ],
+        spawn: (0, 0),
     },
 ];
 
@@ -135,33 +196,113 @@ impl Rotation {
             R3 => R2,
         }
     }
+    pub fn half_turn(&self) -> Rotation {
+        match *self {
+            R0 => R2,
+            R1 => R3,
+            R2 => R0,
+            R3 => R1,
+        }
+    }
+
+    /// Number of right turns from the spawn orientation
+    pub fn quarter_turns(&self) -> usize {
+        match *self {
+            R0 => 0,
+            R1 => 1,
+            R2 => 2,
+            R3 => 3,
+        }
+    }
+
+    pub fn from_quarter_turns(turns: usize) -> Option<Rotation> {
+        [R0, R1, R2, R3].get(turns).copied()
+    }
+}
+
+/// A source of upcoming tetrominoes
+pub trait Randomizer: Iterator<Item = &'static Tetromino> {
+    /// Starts over as if no tetromino had been handed out yet
+    fn clear(&mut self);
+
+    fn box_clone(&self) -> Box<dyn Randomizer>;
+
+    /// The tetrominoes already determined for the coming turns, in order
+    fn remaining(&self) -> Vec<&'static Tetromino>;
+}
+
+impl Clone for Box<dyn Randomizer> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
 }
 
+#[derive(Clone)]
 pub struct TetrominoBag {
     bag: Vec<usize>,
+    rng: StdRng,
 }
 
 impl TetrominoBag {
     pub fn new() -> Self {
         TetrominoBag {
-            bag: Vec::with_capacity(7),
+            bag: Vec::with_capacity(piece_set::shapes().len()),
+            rng: StdRng::from_os_rng(),
+        }
+    }
+
+    /// A bag which always shuffles the same way for the same seed
+    pub fn seeded(seed: u64) -> Self {
+        TetrominoBag {
+            bag: Vec::with_capacity(piece_set::shapes().len()),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    /// A bag which hands out the given tetrominoes before it's refilled
+    pub fn with_remaining(remaining: &[&'static Tetromino]) -> Self {
+        TetrominoBag {
+            bag: remaining
+                .iter()
+                .rev()
+                .filter_map(|&shape| {
+                    piece_set::shapes()
+                        .iter()
+                        .position(|s| std::ptr::eq(s, shape))
+                })
+                .collect(),
+            rng: StdRng::from_os_rng(),
         }
     }
 
     fn valid_start(&self) -> bool {
         // O, S, Z are considered invalid starting tiles
-        if let Some(tetrimino) = SHAPES.get(*self.bag.last().unwrap()) {
+        if let Some(tetrimino) = piece_set::shapes().get(*self.bag.last().unwrap()) {
             match tetrimino.color {
-                Yellow | Lime | Red => return false,
+                Yellow | Lime | Red if tetrimino.is_standard() => return false,
                 _ => return true,
             }
         }
         false
     }
+}
 
-    pub fn clear(&mut self) {
+impl Randomizer for TetrominoBag {
+    fn clear(&mut self) {
         self.bag.clear();
     }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+
+    fn remaining(&self) -> Vec<&'static Tetromino> {
+        self.bag
+            .iter()
+            .rev()
+            .filter_map(|&i| piece_set::shapes().get(i))
+            .collect()
+    }
 }
 
 impl Iterator for TetrominoBag {
@@ -169,13 +310,69 @@ impl Iterator for TetrominoBag {
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.bag.is_empty() {
-            self.bag.extend_from_slice(&[0, 1, 2, 3, 4, 5, 6]);
-            let mut rng = rand::rng();
-            self.bag.shuffle(&mut rng);
+            self.bag.extend(0..piece_set::shapes().len());
+            self.bag.shuffle(&mut self.rng);
             while !self.valid_start() {
-                self.bag.shuffle(&mut rng);
+                self.bag.shuffle(&mut self.rng);
             }
         }
-        SHAPES.get(self.bag.pop().unwrap())
+        piece_set::shapes().get(self.bag.pop().unwrap())
+    }
+}
+
+/// Hands out a predefined sequence of tetrominoes and then runs dry unless
+/// it's followed by a bag
+#[derive(Clone)]
+pub struct FixedSequence {
+    sequence: Vec<&'static Tetromino>,
+    position: usize,
+    then: Option<TetrominoBag>,
+}
+
+impl FixedSequence {
+    pub fn new(sequence: Vec<&'static Tetromino>) -> Self {
+        FixedSequence {
+            sequence,
+            position: 0,
+            then: None,
+        }
+    }
+
+    pub fn followed_by_bag(mut self) -> Self {
+        self.then = Some(TetrominoBag::new());
+        self
+    }
+}
+
+impl Iterator for FixedSequence {
+    type Item = &'static Tetromino;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.sequence.get(self.position) {
+            Some(&tetromino) => {
+                self.position += 1;
+                Some(tetromino)
+            }
+            None => self.then.as_mut().and_then(|bag| bag.next()),
+        }
+    }
+}
+
+impl Randomizer for FixedSequence {
+    fn clear(&mut self) {
+        self.position = 0;
+        if let Some(bag) = self.then.as_mut() {
+            bag.clear();
+        }
+    }
+
+    fn box_clone(&self) -> Box<dyn Randomizer> {
+        Box::new(self.clone())
+    }
+
+    fn remaining(&self) -> Vec<&'static Tetromino> {
+        let mut remaining = self.sequence[self.position.min(self.sequence.len())..].to_vec();
+        remaining.extend(self.then.iter().flat_map(|bag| bag.remaining()));
+        remaining
     }
 }
@@ -0,0 +1,355 @@
+//! The look of the game: the block texture or one sprite per color, the
+//! palette the blocks are tinted with, the background, the side bar colors
+//! and the font of the numbers in the side bar. A theme is chosen once at
+//! start, the built-in default one is the classic look.
+//!
+//! Themes live in `themes/<name>` of the data directory or in any other
+//! directory and are described by its `theme.txt`:
+//!
+//! ```text
+//! # comments start with a hash, files are relative to the directory
+//! block: block.png
+//! background: #202020
+//! cyan: #00c0c0
+//! side bar: #404040
+//! font: digits.ttf
+//! ```
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use image::RgbaImage;
+use piston_window::Glyphs;
+use piston_window::wgpu_graphics::{Texture, TextureContext, TextureSettings};
+
+use crate::storage;
+use crate::tetromino::Color;
+
+static BLOCK_PNG: &[u8] = include_bytes!("../bin/assets/block.png");
+
+const THEME_FILE: &str = "theme.txt";
+
+/// The colors in the order of `Color`, a sprite sheet holds its blocks in
+/// this order from left to right
+static COLOR_NAMES: [&str; 8] = [
+    "cyan", "blue", "orange", "yellow", "lime", "purple", "red", "grey",
+];
+
+static DEFAULT_PALETTE: [[f32; 4]; 8] = [
+    [0.0, 1.0, 1.0, 1.0],
+    [0.0, 0.5, 1.0, 1.0],
+    [1.0, 0.6, 0.0, 1.0],
+    [1.0, 1.0, 0.0, 1.0],
+    [0.5, 1.0, 0.0, 1.0],
+    [0.8, 0.0, 1.0, 1.0],
+    [1.0, 0.0, 0.0, 1.0],
+    [0.3, 0.3, 0.3, 1.0],
+];
+
+const fn rgb(hex: u32) -> [f32; 4] {
+    [
+        (hex >> 16) as f32 / 255.0,
+        ((hex >> 8) & 0xff) as f32 / 255.0,
+        (hex & 0xff) as f32 / 255.0,
+        1.0,
+    ]
+}
+
+/// Palettes which replace the colors of a theme. Those for color vision
+/// deficiencies keep the pieces apart by hue and brightness, the red-green
+/// ones are based on the Okabe-Ito colors.
+static PALETTES: [(&str, [[f32; 4]; 8]); 5] = [
+    ("default", DEFAULT_PALETTE),
+    (
+        "deuteranopia",
+        [
+            rgb(0x56b4e9),
+            rgb(0x0072b2),
+            rgb(0xe69f00),
+            rgb(0xf0e442),
+            rgb(0x009e73),
+            rgb(0xcc79a7),
+            rgb(0xd55e00),
+            rgb(0x5a5a5a),
+        ],
+    ),
+    (
+        // reds look darker without the long wavelength cones, so the red
+        // is replaced by a dark brown next to the bright orange
+        "protanopia",
+        [
+            rgb(0x56b4e9),
+            rgb(0x0072b2),
+            rgb(0xf5b800),
+            rgb(0xf0f0a0),
+            rgb(0x009e73),
+            rgb(0xcc79a7),
+            rgb(0x7a3a00),
+            rgb(0x5a5a5a),
+        ],
+    ),
+    (
+        // blue and green as well as yellow and violet are confused
+        "tritanopia",
+        [
+            rgb(0x00a6a6),
+            rgb(0x2b3aae),
+            rgb(0xff5a36),
+            rgb(0xff9ec4),
+            rgb(0x1e6b2e),
+            rgb(0xb0008a),
+            rgb(0xa8001c),
+            rgb(0x707070),
+        ],
+    ),
+    (
+        "high-contrast",
+        [
+            rgb(0x00ffff),
+            rgb(0x0000ff),
+            rgb(0xff8000),
+            rgb(0xffff00),
+            rgb(0x00ff00),
+            rgb(0xff00ff),
+            rgb(0xff0000),
+            rgb(0x808080),
+        ],
+    ),
+];
+
+static SELECTED: OnceLock<Theme> = OnceLock::new();
+
+/// Colors of the side bar while playing, once a puzzle is solved and when
+/// it failed
+#[derive(Copy, Clone)]
+pub struct SideBar {
+    pub normal: [f32; 4],
+    pub solved: [f32; 4],
+    pub failed: [f32; 4],
+}
+
+pub struct Theme {
+    palette: [[f32; 4]; 8],
+    // the side bar colors given by the theme
+    side_bar: [Option<[f32; 4]>; 3],
+    background: [f32; 4],
+    background_image: Option<RgbaImage>,
+    // a single block tinted by the palette or one sprite per color
+    block: RgbaImage,
+    sprites: Option<Vec<RgbaImage>>,
+    font: Option<PathBuf>,
+}
+
+/// The color of a block in the selected theme
+pub fn palette(color: Color) -> [f32; 4] {
+    SELECTED
+        .get()
+        .map_or(&DEFAULT_PALETTE, |theme| &theme.palette)[color as usize]
+}
+
+/// The side bar colors of the selected theme, by default the grey, lime
+/// and red of the palette
+pub fn side_bar() -> SideBar {
+    let (palette, [normal, solved, failed]) = SELECTED
+        .get()
+        .map_or((&DEFAULT_PALETTE, [None; 3]), |theme| {
+            (&theme.palette, theme.side_bar)
+        });
+    SideBar {
+        normal: normal.unwrap_or(palette[Color::Grey as usize]),
+        solved: solved.unwrap_or(palette[Color::Lime as usize]),
+        failed: failed.unwrap_or(palette[Color::Red as usize]),
+    }
+}
+
+/// The color the window is cleared with
+pub fn background() -> [f32; 4] {
+    SELECTED.get().map_or([1.0; 4], |theme| theme.background)
+}
+
+/// Uses the given theme from now on, only the first call has an effect
+pub fn select(theme: Theme) -> Result<(), String> {
+    SELECTED
+        .set(theme)
+        .map_err(|_| "the theme is already chosen".to_string())
+}
+
+// a color given as #rrggbb or #rrggbbaa
+fn parse_color(value: &str) -> Result<[f32; 4], String> {
+    let invalid = || format!("expected a color like #ff8000 but found '{}'", value);
+    let hex = value.strip_prefix('#').ok_or_else(invalid)?;
+    if !matches!(hex.len(), 6 | 8) || !hex.is_ascii() {
+        return Err(invalid());
+    }
+    let mut rgba = [1.0; 4];
+    for (i, channel) in rgba.iter_mut().enumerate().take(hex.len() / 2) {
+        let byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).map_err(|_| invalid())?;
+        *channel = byte as f32 / 255.0;
+    }
+    Ok(rgba)
+}
+
+fn load_image(path: &Path) -> Result<RgbaImage, String> {
+    Ok(image::open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .to_rgba8())
+}
+
+// cuts a sheet of eight square blocks side by side into the single blocks
+fn split_sprites(sheet: &RgbaImage) -> Result<Vec<RgbaImage>, String> {
+    let size = sheet.height();
+    if size == 0 || sheet.width() != size * COLOR_NAMES.len() as u32 {
+        return Err(format!(
+            "a sprite sheet holds {} square blocks side by side",
+            COLOR_NAMES.len()
+        ));
+    }
+    Ok((0..COLOR_NAMES.len() as u32)
+        .map(|i| image::imageops::crop_imm(sheet, i * size, 0, size, size).to_image())
+        .collect())
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            palette: DEFAULT_PALETTE,
+            side_bar: [None; 3],
+            background: [1.0; 4],
+            background_image: None,
+            block: image::load_from_memory(BLOCK_PNG)
+                .expect("the built-in block is valid")
+                .to_rgba8(),
+            sprites: None,
+            font: None,
+        }
+    }
+}
+
+impl Theme {
+    /// The built-in default theme, an installed theme or the theme in the
+    /// given directory
+    pub fn by_name(name: &str) -> Result<Theme, String> {
+        let dir = Path::new(name);
+        match name {
+            "default" => Ok(Theme::default()),
+            _ if dir.is_dir() => Theme::load(dir),
+            _ => Theme::load(&storage::data_dir().join("themes").join(name)),
+        }
+    }
+
+    pub fn load(dir: &Path) -> Result<Theme, String> {
+        let path = dir.join(THEME_FILE);
+        let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Theme::parse(&text, dir).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    /// Replaces the block colors by one of the built-in palettes
+    pub fn use_palette(&mut self, name: &str) -> Result<(), String> {
+        let (_, palette) = PALETTES.iter().find(|(n, _)| *n == name).ok_or_else(|| {
+            let names: Vec<&str> = PALETTES.iter().map(|(n, _)| *n).collect();
+            format!(
+                "unknown palette '{}', use one of {}",
+                name,
+                names.join(", ")
+            )
+        })?;
+        self.palette = *palette;
+        Ok(())
+    }
+
+    /// Parses the description of a theme, files are looked up in `dir`.
+    /// Everything not mentioned is taken from the default theme.
+    pub fn parse(text: &str, dir: &Path) -> Result<Theme, String> {
+        let mut theme = Theme::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = line
+                .split_once(':')
+                .ok_or_else(|| format!("expected 'key: value' but found '{}'", line))?;
+            let value = value.trim();
+            match key.trim() {
+                "block" => theme.block = load_image(&dir.join(value))?,
+                "sprites" => {
+                    let sheet = load_image(&dir.join(value))?;
+                    theme.sprites =
+                        Some(split_sprites(&sheet).map_err(|e| format!("{}: {}", value, e))?);
+                }
+                "background" if value.starts_with('#') => theme.background = parse_color(value)?,
+                "background" => theme.background_image = Some(load_image(&dir.join(value))?),
+                "palette" => theme.use_palette(value)?,
+                "side bar" => theme.side_bar[0] = Some(parse_color(value)?),
+                "side bar solved" => theme.side_bar[1] = Some(parse_color(value)?),
+                "side bar failed" => theme.side_bar[2] = Some(parse_color(value)?),
+                "font" => {
+                    let font = dir.join(value);
+                    if !font.is_file() {
+                        return Err(format!("{}: no such font", font.display()));
+                    }
+                    theme.font = Some(font);
+                }
+                other => match COLOR_NAMES.iter().position(|&color| color == other) {
+                    Some(i) => theme.palette[i] = parse_color(value)?,
+                    None => return Err(format!("unknown key '{}'", other)),
+                },
+            }
+        }
+        Ok(theme)
+    }
+}
+
+/// The textures of a theme
+pub struct Skin {
+    block: Texture,
+    sprites: Option<Vec<Texture>>,
+    pub background: Option<Texture>,
+    pub font: Option<Glyphs<'static>>,
+}
+
+impl Skin {
+    pub fn new(theme: &Theme, context: &mut TextureContext) -> Result<Skin, String> {
+        let settings = TextureSettings::new();
+        let mut texture = |img: &RgbaImage| {
+            Texture::from_image(context, img, &settings).map_err(|e| e.to_string())
+        };
+        let block = texture(&theme.block)?;
+        let sprites = match theme.sprites.as_ref() {
+            Some(sprites) => Some(sprites.iter().map(&mut texture).collect::<Result<_, _>>()?),
+            None => None,
+        };
+        let background = theme
+            .background_image
+            .as_ref()
+            .map(&mut texture)
+            .transpose()?;
+        let font = match theme.font.as_ref() {
+            Some(path) => Some(
+                Glyphs::new(path, context.clone(), TextureSettings::new())
+                    .map_err(|e| format!("{}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        Ok(Skin {
+            block,
+            sprites,
+            background,
+            font,
+        })
+    }
+
+    /// The texture of a block and the color to draw it with
+    pub fn block(&self, color: Color) -> (&Texture, [f32; 4]) {
+        match self.sprites.as_ref() {
+            Some(sprites) => (&sprites[color as usize], [1.0; 4]),
+            None => (&self.block, color.as_rgba()),
+        }
+    }
+
+    /// The plain block texture, tinted freely for effects
+    pub fn plain_block(&self) -> &Texture {
+        &self.block
+    }
+}
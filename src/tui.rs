@@ -0,0 +1,327 @@
+//! Plays the game in a terminal: the board is drawn with block characters
+//! in ANSI colors and the side bar as text, the keys are read raw from the
+//! standard input. Terminals report no key releases, so every key press is
+//! released right away except for soft dropping, which lasts while the
+//! terminal repeats the key.
+
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+use piston_window::{Key, UpdateArgs};
+
+use crate::app::App;
+use crate::menu::View;
+use crate::tetris::{Frame, Message};
+use crate::tetromino::{Rotation, Tetromino};
+
+/// Time between two redraws of the terminal
+const FRAME_TIME: Duration = Duration::from_millis(33);
+/// Soft dropping stops when the key hasn't been repeated for this long,
+/// terminals wait about half a second before repeating a held key
+const SOFT_DROP_HOLD: Duration = Duration::from_millis(600);
+
+const ESC: u8 = 0x1b;
+const CTRL_C: u8 = 0x03;
+
+/// The terminal in raw mode on the alternate screen, restored when dropped
+struct RawTerminal {
+    original: libc::termios,
+}
+
+impl RawTerminal {
+    fn enable() -> io::Result<RawTerminal> {
+        // SAFETY: termios is a plain C struct filled in by tcgetattr
+        let mut termios: libc::termios = unsafe { std::mem::zeroed() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let original = termios;
+        unsafe { libc::cfmakeraw(&mut termios) };
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &termios) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // from here on dropping the guard restores the terminal, also when
+        // the output below fails
+        let terminal = RawTerminal { original };
+        // switch to the alternate screen and hide the cursor
+        print!("\x1b[?1049h\x1b[?25l\x1b[2J");
+        io::stdout().flush()?;
+        Ok(terminal)
+    }
+
+    /// The bytes typed within `timeout`, nothing if there were none
+    fn read(&self, timeout: Duration) -> io::Result<Vec<u8>> {
+        let mut poll = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let ready = unsafe { libc::poll(&mut poll, 1, timeout.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            return Ok(Vec::new());
+        }
+        // read the file descriptor directly, the buffered stdin could keep
+        // bytes back which poll doesn't know of
+        let mut buffer = [0u8; 64];
+        let count =
+            unsafe { libc::read(libc::STDIN_FILENO, buffer.as_mut_ptr().cast(), buffer.len()) };
+        if count < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(buffer[..count as usize].to_vec())
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        print!("\x1b[0m\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original) };
+    }
+}
+
+// the function keys by their number in `ESC [ <number> ~`
+fn function_key(number: &[u8]) -> Option<Key> {
+    match number {
+        b"11" => Some(Key::F1),
+        b"12" => Some(Key::F2),
+        b"13" => Some(Key::F3),
+        b"14" => Some(Key::F4),
+        b"15" => Some(Key::F5),
+        b"17" => Some(Key::F6),
+        b"18" => Some(Key::F7),
+        b"19" => Some(Key::F8),
+        b"20" => Some(Key::F9),
+        b"21" => Some(Key::F10),
+        b"23" => Some(Key::F11),
+        b"24" => Some(Key::F12),
+        _ => None,
+    }
+}
+
+/// The keys within the typed bytes and whether the player wants to quit
+/// with Ctrl-C
+fn parse_keys(bytes: &[u8]) -> (Vec<Key>, bool) {
+    let mut keys = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i..] {
+            [ESC, b'[' | b'O', code, ..] if matches!(code, b'A'..=b'D' | b'P'..=b'S') => {
+                keys.push(match code {
+                    b'A' => Key::Up,
+                    b'B' => Key::Down,
+                    b'C' => Key::Right,
+                    b'D' => Key::Left,
+                    b'P' => Key::F1,
+                    b'Q' => Key::F2,
+                    b'R' => Key::F3,
+                    _ => Key::F4,
+                });
+                i += 3;
+            }
+            [ESC, b'[', ..] => {
+                // a sequence like ESC [ 1 9 ~ ends with a tilde
+                let rest = &bytes[i + 2..];
+                let end = rest.iter().position(|&b| !b.is_ascii_digit());
+                match end {
+                    Some(end) if rest[end] == b'~' => {
+                        keys.extend(function_key(&rest[..end]));
+                        i += 3 + end;
+                    }
+                    _ => i = bytes.len(),
+                }
+            }
+            [ESC] => {
+                keys.push(Key::Escape);
+                i += 1;
+            }
+            [CTRL_C, ..] => return (keys, true),
+            // an unknown escape sequence
+            [ESC, ..] => i = bytes.len(),
+            [byte, ..] => {
+                let key = match byte {
+                    b' ' => Some(Key::Space),
+                    b'\r' => Some(Key::Return),
+                    0x7f => Some(Key::Backspace),
+                    // letters and digits are encoded by their lower case character
+                    _ if byte.is_ascii_alphanumeric() => {
+                        Some(Key::from(byte.to_ascii_lowercase() as u32))
+                    }
+                    // the editor needs a mouse
+                    _ => None,
+                };
+                keys.extend(key);
+                i += 1;
+            }
+            [] => break,
+        }
+    }
+    (keys, false)
+}
+
+fn block(color: [f32; 4]) -> String {
+    let [r, g, b, _] = color.map(|c| (c * 255.0).round() as u8);
+    format!("\x1b[38;2;{};{};{}m██\x1b[0m", r, g, b)
+}
+
+// the lines showing a piece, as large as its blocks need
+fn shape_lines(shape: &'static Tetromino) -> Vec<String> {
+    let points = shape.points(Rotation::R0);
+    let left = points.iter().map(|&(x, _)| x).min().unwrap_or(0);
+    let right = points.iter().map(|&(x, _)| x + 1).max().unwrap_or(0);
+    let top = points.iter().map(|&(_, y)| y).min().unwrap_or(0);
+    let bottom = points.iter().map(|&(_, y)| y + 1).max().unwrap_or(0);
+    (top..bottom)
+        .map(|y| {
+            let cells: String = (left..right)
+                .map(|x| {
+                    if points.contains(&(x, y)) {
+                        block(shape.get_color().as_rgba())
+                    } else {
+                        "  ".to_string()
+                    }
+                })
+                .collect();
+            format!(" {}", cells)
+        })
+        .collect()
+}
+
+fn side_bar(frame: &Frame) -> Vec<String> {
+    let mut lines = vec![" Next".to_string()];
+    lines.extend(frame.next.map(shape_lines).unwrap_or_default());
+    lines.push(String::new());
+    lines.push(" Hold".to_string());
+    lines.extend(frame.hold.map(shape_lines).unwrap_or_default());
+    lines.push(String::new());
+    lines.push(format!(" Lines {}", frame.lines));
+    if let Some(level) = frame.level {
+        lines.push(format!(" Level {}", level));
+    }
+    if let Some(puzzle) = frame.puzzle {
+        lines.push(format!(" Puzzle {}", puzzle));
+    }
+    if let Some(status) = frame.status {
+        lines.push(String::new());
+        lines.push(format!(" \x1b[1m{}\x1b[0m", status));
+    }
+    lines
+}
+
+// the open menu as plain text, the selected line marked
+fn draw_menu(view: &View, footer: &str) -> String {
+    let mut screen = format!(
+        "\x1b[H\r\n \x1b[1m{}\x1b[0m\x1b[K\r\n\x1b[K\r\n",
+        view.title
+    );
+    for (i, line) in view.lines.iter().enumerate() {
+        if Some(i) == view.selected {
+            screen.push_str(&format!(" \x1b[1m> {}\x1b[0m", line));
+        } else {
+            screen.push_str(&format!("   {}", line));
+        }
+        screen.push_str("\x1b[K\r\n");
+    }
+    screen.push_str(&format!("\x1b[K\r\n\x1b[2m{}\x1b[0m\x1b[K\x1b[J", footer));
+    screen
+}
+
+/// The whole screen, each line cleared to its end, with the first line of
+/// the last message at the bottom
+fn draw(frame: &Frame, message: Option<&Message>) -> String {
+    let note = message.and_then(|message| message.text().lines().next());
+    if let Some(view) = frame.menu.as_ref() {
+        return draw_menu(
+            view,
+            note.unwrap_or("Arrows select, Enter chooses, Ctrl-C quits"),
+        );
+    }
+    let width = frame.rows.first().map_or(0, |row| row.len());
+    let side_bar = side_bar(frame);
+    let mut screen = String::from("\x1b[H");
+    for (y, row) in frame.rows.iter().enumerate() {
+        screen.push('│');
+        for cell in row {
+            match cell {
+                Some(_) if frame.clearing.contains(&y) => screen.push_str(&block([1.0; 4])),
+                Some(color) => screen.push_str(&block(color.as_rgba())),
+                None => screen.push_str("\x1b[2m .\x1b[0m"),
+            }
+        }
+        screen.push('│');
+        screen.push_str(side_bar.get(y).map_or("", |line| line));
+        screen.push_str("\x1b[K\r\n");
+    }
+    screen.push('└');
+    screen.push_str(&"──".repeat(width));
+    screen.push('┘');
+    screen.push_str(&format!(
+        "\x1b[K\r\n\x1b[2m{}\x1b[0m\x1b[K\x1b[J",
+        note.unwrap_or("Esc menu, Ctrl-C quits")
+    ));
+    screen
+}
+
+/// Plays until the player quits in the menu or with Ctrl-C. The messages of
+/// the game are printed in full once the terminal is restored.
+pub fn run(app: &mut App) -> Result<(), String> {
+    let error = |e: io::Error| format!("terminal: {}", e);
+    let terminal = RawTerminal::enable().map_err(error)?;
+    let mut last_update = Instant::now();
+    let mut last_screen = String::new();
+    // the soft drop key and when it is released unless repeated
+    let mut soft_drop: Option<(Key, Instant)> = None;
+    let mut messages = Vec::new();
+    loop {
+        let (keys, quit) = parse_keys(&terminal.read(FRAME_TIME).map_err(error)?);
+        let now = Instant::now();
+        for key in keys {
+            match key {
+                // in the menu Down and S move the selection with every press
+                Key::Down | Key::S if !app.menu_open() => {
+                    if soft_drop.is_none() {
+                        app.key_press(&key);
+                    }
+                    soft_drop = Some((key, now + SOFT_DROP_HOLD));
+                }
+                _ => {
+                    // the soft drop ends before the menu opens
+                    if key == Key::Escape
+                        && !app.menu_open()
+                        && let Some((held, _)) = soft_drop.take()
+                    {
+                        app.key_release(&held);
+                    }
+                    app.key_press(&key);
+                    app.key_release(&key);
+                }
+            }
+        }
+        if let Some((key, until)) = soft_drop
+            && now >= until
+        {
+            app.key_release(&key);
+            soft_drop = None;
+        }
+        app.update(&UpdateArgs {
+            dt: (now - last_update).as_secs_f64(),
+        });
+        last_update = now;
+        messages.extend(app.game_mut().take_messages());
+        if quit || app.quit_requested() {
+            break;
+        }
+        let screen = draw(&app.frame(), messages.last());
+        if screen != last_screen {
+            let mut stdout = io::stdout();
+            stdout.write_all(screen.as_bytes()).map_err(error)?;
+            stdout.flush().map_err(error)?;
+            last_screen = screen;
+        }
+    }
+    drop(terminal);
+    for message in messages {
+        message.print();
+    }
+    Ok(())
+}